@@ -13,7 +13,35 @@ const COMMANDS: &[&str] = &[
     "update",
     "update_all",
     "update_one",
-    "list_collections"
+    "replace_one",
+    "find_one_and_update",
+    "find_one_and_replace",
+    "find_one_and_delete",
+    "list_collections",
+    "begin_transaction",
+    "commit_transaction",
+    "abort_transaction",
+    "count",
+    "estimated_count",
+    "distinct",
+    "aggregate",
+    "create_index",
+    "drop_index",
+    "list_indexes",
+    "open_cursor",
+    "next_batch",
+    "close_cursor",
+    "drop_collection",
+    "subscribe",
+    "unsubscribe",
+    "watch_query",
+    "unwatch",
+    "list_known_databases",
+    "forget_database",
+    "open_memory_database",
+    "save_as",
+    "daemon_status",
+    "cancel"
 ];
 
 fn main() {
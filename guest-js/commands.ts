@@ -1,5 +1,21 @@
-import { invoke, InvokeArgs } from "@tauri-apps/api/core";
-import { Result } from "./types";
+import { Channel, invoke, InvokeArgs } from "@tauri-apps/api/core";
+import {
+    ChangeFilter,
+    ChangeRecord,
+    CursorBatch,
+    DaemonStatus,
+    FindAndModifyOptions,
+    FindOptions,
+    IndexDefinition,
+    KnownDatabase,
+    LiveQuery,
+    LiveQueryMode,
+    LiveQueryUpdate,
+    PolodbError,
+    RawDocumentId,
+    RequestOptions,
+    Result,
+} from "./types";
 
 async function exec<R = any>(
     command: string,
@@ -11,21 +27,20 @@ async function exec<R = any>(
             data: await invoke<R>(`plugin:polodb|${command}`, args),
         };
     } catch (e) {
-        try {
+        const error = e as Partial<PolodbError> | null;
+        if (error && typeof error === "object" && error.code) {
             return {
                 success: false,
-                error: Object.keys(e as object)[0],
-                context: (e as { [key: string]: any })[
-                    Object.keys(e as object)[0]
-                ],
-            };
-        } catch (u) {
-            return {
-                success: false,
-                error: "unknown",
-                context: u,
+                error: error.code,
+                message: error.message ?? error.code,
+                context: error as PolodbError,
             };
         }
+        return {
+            success: false,
+            error: "unknown",
+            message: String(e),
+        };
     }
 }
 
@@ -33,6 +48,24 @@ export async function list_databases(): Promise<Result<string[]>> {
     return await exec<string[]>("list_databases");
 }
 
+export async function list_known_databases(): Promise<
+    Result<KnownDatabase[]>
+> {
+    return await exec<KnownDatabase[]>("list_known_databases");
+}
+
+export async function forget_database(key: string): Promise<Result<boolean>> {
+    return await exec<boolean>("forget_database", { key });
+}
+
+export async function daemon_status(): Promise<Result<DaemonStatus>> {
+    return await exec<DaemonStatus>("daemon_status");
+}
+
+export async function cancel(request: string): Promise<Result<boolean>> {
+    return await exec<boolean>("cancel", { request });
+}
+
 export async function list_collections(
     database: string
 ): Promise<Result<string[]>> {
@@ -41,29 +74,80 @@ export async function list_collections(
 
 export async function open_database(
     key: string,
-    path: string
+    path: string,
+    readOnly?: boolean,
+    request?: RequestOptions | null
 ): Promise<Result<string>> {
-    return await exec<string>("open_database", { key, path });
+    return await exec<string>("open_database", {
+        key,
+        path,
+        readOnly: readOnly ?? false,
+        request: request ?? null,
+    });
 }
 
-export async function close_database(key: string): Promise<Result<string>> {
-    return await exec<string>("close_database", { key });
+export async function open_memory_database(
+    key: string,
+    request?: RequestOptions | null
+): Promise<Result<string>> {
+    return await exec<string>("open_memory_database", {
+        key,
+        request: request ?? null,
+    });
+}
+
+export async function save_as(
+    key: string,
+    path: string,
+    request?: RequestOptions | null
+): Promise<Result<string>> {
+    return await exec<string>("save_as", {
+        key,
+        path,
+        request: request ?? null,
+    });
+}
+
+export async function close_database(
+    key: string,
+    request?: RequestOptions | null
+): Promise<Result<string>> {
+    return await exec<string>("close_database", {
+        key,
+        request: request ?? null,
+    });
 }
 
 export async function insert<T extends object = any>(
     database: string,
     collection: string,
-    documents: T[]
-): Promise<Result<number>> {
-    return await exec<number>("insert", { database, collection, documents });
+    documents: T[],
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<RawDocumentId[]>> {
+    return await exec<RawDocumentId[]>("insert", {
+        database,
+        collection,
+        documents,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
 }
 
 export async function insert_one<T extends object = any>(
     database: string,
     collection: string,
-    document: T
-): Promise<Result<number>> {
-    return await exec<number>("insert_one", { database, collection, document });
+    document: T,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<RawDocumentId>> {
+    return await exec<RawDocumentId>("insert_one", {
+        database,
+        collection,
+        document,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
 }
 
 export async function find_many<
@@ -74,13 +158,21 @@ export async function find_many<
     database: string,
     collection: string,
     query: Query,
-    sorting?: Sorting | null
+    sorting?: Sorting | null,
+    options?: FindOptions | null,
+    transaction?: string | null,
+    request?: RequestOptions | null
 ): Promise<Result<Document[]>> {
     return await exec<Document[]>("find", {
         database,
         collection,
         query,
-        sorting: sorting ?? null,
+        sort: sorting ?? null,
+        skip: options?.skip ?? null,
+        limit: options?.limit ?? null,
+        projection: options?.projection ?? null,
+        transaction: transaction ?? null,
+        request: request ?? null,
     });
 }
 
@@ -90,61 +182,243 @@ export async function find_all<
 >(
     database: string,
     collection: string,
-    sorting?: Sorting | null
+    sorting?: Sorting | null,
+    options?: FindOptions | null,
+    transaction?: string | null,
+    request?: RequestOptions | null
 ): Promise<Result<Document[]>> {
     return await exec<Document[]>("find_all", {
         database,
         collection,
-        sorting: sorting ?? null,
+        sort: sorting ?? null,
+        skip: options?.skip ?? null,
+        limit: options?.limit ?? null,
+        projection: options?.projection ?? null,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function open_cursor<
+    Query extends object = any,
+    Sorting extends object = any
+>(
+    database: string,
+    collection: string,
+    query: Query,
+    sorting?: Sorting | null,
+    options?: FindOptions | null,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<string>> {
+    return await exec<string>("open_cursor", {
+        database,
+        collection,
+        query,
+        sort: sorting ?? null,
+        skip: options?.skip ?? null,
+        limit: options?.limit ?? null,
+        projection: options?.projection ?? null,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function next_batch<Document extends object = any>(
+    cursor: string,
+    size: number,
+    request?: RequestOptions | null
+): Promise<Result<CursorBatch<Document>>> {
+    return await exec<CursorBatch<Document>>("next_batch", {
+        cursor,
+        size,
+        request: request ?? null,
     });
 }
 
+export async function close_cursor(cursor: string): Promise<Result<null>> {
+    return await exec<null>("close_cursor", { cursor });
+}
+
 export async function find_one<
     Document extends object = any,
-    Query extends object = any
+    Query extends object = any,
+    Sorting extends object = any
 >(
     database: string,
     collection: string,
-    query: Query
-): Promise<Result<Document>> {
-    return await exec<Document>("find_one", {
+    query: Query,
+    sorting?: Sorting | null,
+    options?: Omit<FindOptions, "limit"> | null,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<Document | null>> {
+    return await exec<Document | null>("find_one", {
+        database,
+        collection,
+        query,
+        sort: sorting ?? null,
+        skip: options?.skip ?? null,
+        projection: options?.projection ?? null,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function count<Query extends object = any>(
+    database: string,
+    collection: string,
+    query: Query,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<number>> {
+    return await exec<number>("count", {
+        database,
+        collection,
+        query,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function estimated_count(
+    database: string,
+    collection: string,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<number>> {
+    return await exec<number>("estimated_count", {
+        database,
+        collection,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function distinct<Value = any, Query extends object = any>(
+    database: string,
+    collection: string,
+    field: string,
+    query: Query,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<Value[]>> {
+    return await exec<Value[]>("distinct", {
         database,
         collection,
+        field,
         query,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function aggregate<
+    Document extends object = any,
+    Stage extends object = any
+>(
+    database: string,
+    collection: string,
+    pipeline: Stage[],
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<Document[]>> {
+    return await exec<Document[]>("aggregate", {
+        database,
+        collection,
+        pipeline,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function create_index(
+    database: string,
+    collection: string,
+    index: IndexDefinition,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<string>> {
+    return await exec<string>("create_index", {
+        database,
+        collection,
+        index,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function drop_index(
+    database: string,
+    collection: string,
+    name: string,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<null>> {
+    return await exec<null>("drop_index", {
+        database,
+        collection,
+        name,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function list_indexes(
+    database: string,
+    collection: string,
+    request?: RequestOptions | null
+): Promise<Result<IndexDefinition[]>> {
+    return await exec<IndexDefinition[]>("list_indexes", {
+        database,
+        collection,
+        request: request ?? null,
     });
 }
 
 export async function delete_many<Query extends object = any>(
     database: string,
     collection: string,
-    query: Query
+    query: Query,
+    transaction?: string | null,
+    request?: RequestOptions | null
 ): Promise<Result<number>> {
     return await exec<number>("delete", {
         database,
         collection,
         query,
+        transaction: transaction ?? null,
+        request: request ?? null,
     });
 }
 
 export async function delete_all(
     database: string,
-    collection: string
+    collection: string,
+    transaction?: string | null,
+    request?: RequestOptions | null
 ): Promise<Result<number>> {
     return await exec<number>("delete_all", {
         database,
         collection,
+        transaction: transaction ?? null,
+        request: request ?? null,
     });
 }
 
 export async function delete_one<Query extends object = any>(
     database: string,
     collection: string,
-    query: Query
+    query: Query,
+    transaction?: string | null,
+    request?: RequestOptions | null
 ): Promise<Result<number>> {
     return await exec<number>("delete_one", {
         database,
         collection,
         query,
+        transaction: transaction ?? null,
+        request: request ?? null,
     });
 }
 
@@ -156,7 +430,9 @@ export async function update_many<
     collection: string,
     query: Query,
     update: Update,
-    upsert?: boolean
+    upsert?: boolean,
+    transaction?: string | null,
+    request?: RequestOptions | null
 ): Promise<Result<number>> {
     return await exec<number>("update", {
         database,
@@ -164,6 +440,8 @@ export async function update_many<
         query,
         update,
         upsert: upsert ?? false,
+        transaction: transaction ?? null,
+        request: request ?? null,
     });
 }
 
@@ -171,13 +449,17 @@ export async function update_all<Update extends object = any>(
     database: string,
     collection: string,
     update: Update,
-    upsert?: boolean
+    upsert?: boolean,
+    transaction?: string | null,
+    request?: RequestOptions | null
 ): Promise<Result<number>> {
     return await exec<number>("update_all", {
         database,
         collection,
         update,
         upsert: upsert ?? false,
+        transaction: transaction ?? null,
+        request: request ?? null,
     });
 }
 
@@ -189,7 +471,9 @@ export async function update_one<
     collection: string,
     query: Query,
     update: Update,
-    upsert?: boolean
+    upsert?: boolean,
+    transaction?: string | null,
+    request?: RequestOptions | null
 ): Promise<Result<number>> {
     return await exec<number>("update_one", {
         database,
@@ -197,5 +481,188 @@ export async function update_one<
         query,
         update,
         upsert: upsert ?? false,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function replace_one<
+    Document extends object = any,
+    Query extends object = any
+>(
+    database: string,
+    collection: string,
+    query: Query,
+    replacement: Document,
+    upsert?: boolean,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<number>> {
+    return await exec<number>("replace_one", {
+        database,
+        collection,
+        query,
+        replacement,
+        upsert: upsert ?? false,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function find_one_and_update<
+    Document extends object = any,
+    Query extends object = any,
+    Update extends object = any,
+    Sorting extends object = any
+>(
+    database: string,
+    collection: string,
+    query: Query,
+    update: Update,
+    sorting?: Sorting | null,
+    options?: FindAndModifyOptions | null,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<Document | null>> {
+    return await exec<Document | null>("find_one_and_update", {
+        database,
+        collection,
+        query,
+        update,
+        sort: sorting ?? null,
+        projection: options?.projection ?? null,
+        upsert: options?.upsert ?? null,
+        returnDocument: options?.returnDocument ?? null,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function find_one_and_replace<
+    Document extends object = any,
+    Query extends object = any,
+    Sorting extends object = any
+>(
+    database: string,
+    collection: string,
+    query: Query,
+    replacement: Document,
+    sorting?: Sorting | null,
+    options?: FindAndModifyOptions | null,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<Document | null>> {
+    return await exec<Document | null>("find_one_and_replace", {
+        database,
+        collection,
+        query,
+        replacement,
+        sort: sorting ?? null,
+        projection: options?.projection ?? null,
+        upsert: options?.upsert ?? null,
+        returnDocument: options?.returnDocument ?? null,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function find_one_and_delete<
+    Document extends object = any,
+    Query extends object = any,
+    Sorting extends object = any
+>(
+    database: string,
+    collection: string,
+    query: Query,
+    sorting?: Sorting | null,
+    options?: Pick<FindAndModifyOptions, "projection"> | null,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<Document | null>> {
+    return await exec<Document | null>("find_one_and_delete", {
+        database,
+        collection,
+        query,
+        sort: sorting ?? null,
+        projection: options?.projection ?? null,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function begin_transaction(
+    database: string
+): Promise<Result<string>> {
+    return await exec<string>("begin_transaction", { database });
+}
+
+export async function commit_transaction(
+    transaction: string
+): Promise<Result<null>> {
+    return await exec<null>("commit_transaction", { transaction });
+}
+
+export async function abort_transaction(
+    transaction: string
+): Promise<Result<null>> {
+    return await exec<null>("abort_transaction", { transaction });
+}
+
+export async function drop_collection(
+    database: string,
+    collection: string,
+    transaction?: string | null,
+    request?: RequestOptions | null
+): Promise<Result<null>> {
+    return await exec<null>("drop_collection", {
+        database,
+        collection,
+        transaction: transaction ?? null,
+        request: request ?? null,
+    });
+}
+
+export async function subscribe<Document extends object = any>(
+    filter: ChangeFilter | null,
+    onChange: (change: ChangeRecord<Document>) => void
+): Promise<Result<string>> {
+    const channel = new Channel<ChangeRecord<Document>>();
+    channel.onmessage = onChange;
+    return await exec<string>("subscribe", { filter, channel });
+}
+
+export async function unsubscribe(subscription: string): Promise<Result<null>> {
+    return await exec<null>("unsubscribe", { subscription });
+}
+
+export async function watch_query<
+    Document extends object = any,
+    Query extends object = any,
+    Sorting extends object = any
+>(
+    database: string,
+    collection: string,
+    query: Query,
+    onUpdate: (update: LiveQueryUpdate<Document>) => void,
+    sorting?: Sorting | null,
+    options?: FindOptions | null,
+    mode?: LiveQueryMode | null
+): Promise<Result<LiveQuery<Document>>> {
+    const channel = new Channel<LiveQueryUpdate<Document>>();
+    channel.onmessage = onUpdate;
+    return await exec<LiveQuery<Document>>("watch_query", {
+        database,
+        collection,
+        query,
+        sort: sorting ?? null,
+        skip: options?.skip ?? null,
+        limit: options?.limit ?? null,
+        projection: options?.projection ?? null,
+        mode: mode ?? null,
+        channel,
     });
 }
+
+export async function unwatch(query: string): Promise<Result<null>> {
+    return await exec<null>("unwatch", { query });
+}
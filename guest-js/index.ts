@@ -14,17 +14,46 @@ import {
     update_many,
     update_one,
     list_collections,
+    begin_transaction,
+    commit_transaction,
+    abort_transaction,
+    aggregate,
+    create_index,
+    drop_index,
+    list_indexes,
+    open_cursor,
+    next_batch,
+    close_cursor,
+    drop_collection,
+    subscribe,
+    unsubscribe,
+    watch_query,
+    unwatch,
 } from "./commands";
 
 import {
     Result,
     ResultFailure,
     ResultSuccess,
+    ObjectId,
+    RawDocumentId,
+    DocumentId,
+    IndexDefinition,
+    FindOptions,
+    CursorBatch,
+    ChangeOperation,
+    ChangeRecord,
+    ChangeFilter,
+    LiveQuery,
+    LiveQueryMode,
+    LiveQueryUpdate,
     isError,
     isSuccess,
+    isObjectId,
+    normalizeId,
 } from "./types";
 
-import { Database, Collection, Document } from "./wrapper";
+import { Database, Collection, Cursor, Document } from "./wrapper";
 
 export {
     list_databases,
@@ -43,10 +72,44 @@ export {
     update_one,
     isError,
     isSuccess,
+    isObjectId,
+    normalizeId,
     list_collections,
+    begin_transaction,
+    commit_transaction,
+    abort_transaction,
+    aggregate,
+    create_index,
+    drop_index,
+    list_indexes,
+    open_cursor,
+    next_batch,
+    close_cursor,
+    drop_collection,
+    subscribe,
+    unsubscribe,
+    watch_query,
+    unwatch,
     Database,
     Collection,
+    Cursor,
     Document,
 };
 
-export type { Result, ResultFailure, ResultSuccess };
+export type {
+    Result,
+    ResultFailure,
+    ResultSuccess,
+    ObjectId,
+    RawDocumentId,
+    DocumentId,
+    IndexDefinition,
+    FindOptions,
+    CursorBatch,
+    ChangeOperation,
+    ChangeRecord,
+    ChangeFilter,
+    LiveQuery,
+    LiveQueryMode,
+    LiveQueryUpdate,
+};
@@ -3,14 +3,154 @@ export type ResultSuccess<T> = {
     data: T;
 };
 
+export type ErrorCode =
+    | "sync"
+    | "daemon"
+    | "panicked"
+    | "timeout"
+    | "cancelled"
+    | "busy"
+    | "unknown_database"
+    | "read_only"
+    | "closed"
+    | "locked"
+    | "corrupted"
+    | "io"
+    | "path_forbidden"
+    | "permission_denied"
+    | "not_found"
+    | "duplicate_key"
+    | "invalid_query"
+    | "invalid_update"
+    | "invalid_index"
+    | "serialization"
+    | "unknown_transaction"
+    | "transaction"
+    | "unknown_cursor"
+    | "unknown_subscription"
+    | "database"
+    | "unknown";
+
+export type PolodbError = {
+    code: ErrorCode;
+    message: string;
+    [field: string]: any;
+};
+
 export type ResultFailure = {
     success: false;
-    error: string;
-    context?: any;
+    error: ErrorCode;
+    message: string;
+    context?: PolodbError;
 };
 
 export type Result<T> = ResultSuccess<T> | ResultFailure;
 
+export type ObjectId = {
+    $oid: string;
+};
+
+export type RawDocumentId = ObjectId | string | number;
+
+export type DocumentId = ObjectId | string | number;
+
+export type IndexDefinition = {
+    keys: { [key: string]: 1 };
+    name?: string | null;
+    unique?: boolean;
+};
+
+export type KnownDatabase = {
+    key: string;
+    file: string;
+    readOnly: boolean;
+};
+
+export type Priority = "high" | "normal" | "low";
+
+export type RequestOptions = {
+    id?: string | null;
+    timeout?: number | null;
+    priority?: Priority | null;
+};
+
+export type DatabaseStatus = {
+    database: string;
+    queued: number;
+    byPriority: { high: number; normal: number; low: number };
+    waiting: number;
+    running: boolean;
+};
+
+export type FailedDatabase = {
+    database: string;
+    error: PolodbError;
+};
+
+export type DaemonStatus = {
+    uptime: number;
+    restarts: number;
+    queued: number;
+    capacity: number;
+    rejected: number;
+    closing: boolean;
+    databases: DatabaseStatus[];
+    failed: FailedDatabase[];
+};
+
+export type FindOptions = {
+    skip?: number | null;
+    limit?: number | null;
+    projection?: { [key: string]: 0 | 1 | boolean } | null;
+};
+
+export type ReturnDocument = "before" | "after";
+
+export type FindAndModifyOptions = {
+    projection?: { [key: string]: 0 | 1 | boolean } | null;
+    upsert?: boolean | null;
+    returnDocument?: ReturnDocument | null;
+};
+
+export type CursorBatch<T> = {
+    documents: T[];
+    exhausted: boolean;
+};
+
+export type ChangeOperation = "insert" | "update" | "delete" | "drop";
+
+export type ChangeRecord<T extends object = any> = {
+    database: string;
+    collection: string;
+    operation: ChangeOperation;
+    ids: RawDocumentId[];
+    documents?: T[] | null;
+};
+
+export type ChangeFilter = {
+    database?: string | null;
+    collection?: string | null;
+    operations?: ChangeOperation[] | null;
+    documents?: boolean;
+};
+
+export type LiveQueryMode = "results" | "diff";
+
+export type LiveQuery<T extends object = any> = {
+    id: string;
+    documents: T[];
+};
+
+export type LiveQueryUpdate<T extends object = any> =
+    | { type: "results"; documents: T[] }
+    | {
+          type: "diff";
+          added: T[];
+          removed: RawDocumentId[];
+          changed: T[];
+      }
+    | { type: "error"; error: PolodbError };
+
 export function isSuccess<T>(result: Result<T>): result is ResultSuccess<T> {
     return result.success;
 }
@@ -18,3 +158,17 @@ export function isSuccess<T>(result: Result<T>): result is ResultSuccess<T> {
 export function isError(result: Result<any>): result is ResultFailure {
     return !result.success;
 }
+
+export function isObjectId(id: any): id is ObjectId {
+    return !!id && typeof id === "object" && typeof id["$oid"] === "string";
+}
+
+export function normalizeId(id: any): DocumentId | null {
+    if (typeof id === "string" || typeof id === "number") {
+        return id;
+    }
+    if (isObjectId(id)) {
+        return { $oid: id.$oid };
+    }
+    return null;
+}
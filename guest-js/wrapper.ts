@@ -1,39 +1,93 @@
 import { omit } from "lodash";
 import {
+    abort_transaction,
+    aggregate,
+    begin_transaction,
+    cancel,
+    close_cursor,
     close_database,
+    commit_transaction,
+    count,
+    create_index,
     delete_all,
     delete_many,
     delete_one,
+    distinct,
+    drop_collection,
+    drop_index,
+    estimated_count,
     find_all,
     find_many,
     find_one,
+    find_one_and_delete,
+    find_one_and_replace,
+    find_one_and_update,
+    forget_database,
     insert,
     list_collections,
     list_databases,
+    list_indexes,
+    list_known_databases,
+    next_batch,
+    open_cursor,
     open_database,
+    open_memory_database,
+    replace_one,
+    save_as,
+    subscribe,
+    unsubscribe,
+    unwatch,
     update_all,
     update_many,
     update_one,
+    watch_query,
 } from "./commands";
 import { PartialDeep } from "type-fest";
+import {
+    ChangeFilter,
+    ChangeRecord,
+    DocumentId,
+    FindAndModifyOptions,
+    FindOptions,
+    IndexDefinition,
+    KnownDatabase,
+    LiveQueryMode,
+    LiveQueryUpdate,
+    normalizeId,
+} from "./types";
 
 export type Document<T extends object = any> = {
-    _id: string | null;
+    _id: DocumentId | null;
 } & T;
 
+/** Sends a query's `_id` the way the daemon stores it, whichever form it was given in. */
+function withWireId<Query extends object>(query: Query): Query {
+    if (!Object.keys(query).includes("_id")) {
+        return query;
+    }
+    const id = normalizeId((query as any)._id);
+    return id === null ? query : ({ ...query, _id: id } as Query);
+}
+
 export class Database {
     private _key: string;
     private _closed: boolean;
+    private _transaction: string | null;
 
-    private constructor(key: string) {
+    private constructor(key: string, transaction: string | null = null) {
         this._key = key;
         this._closed = false;
+        this._transaction = transaction;
     }
 
     public get key() {
         return this._key;
     }
 
+    public get transactionId() {
+        return this._transaction;
+    }
+
     public get closed() {
         return this._closed;
     }
@@ -46,9 +100,15 @@ export class Database {
 
     public static async open(
         key: string,
-        path: string
+        path: string,
+        readOnly?: boolean
     ): Promise<Database | null> {
-        const db = await open_database(key, path);
+        const db = await open_database(key, path, readOnly);
+        return db.success ? new Database(key) : null;
+    }
+
+    public static async memory(key: string): Promise<Database | null> {
+        const db = await open_memory_database(key);
         return db.success ? new Database(key) : null;
     }
 
@@ -57,6 +117,21 @@ export class Database {
         return result.success ? result.data : [];
     }
 
+    public static async list_known_databases(): Promise<KnownDatabase[]> {
+        const result = await list_known_databases();
+        return result.success ? result.data : [];
+    }
+
+    public static async cancel(request: string): Promise<boolean> {
+        const result = await cancel(request);
+        return result.success && result.data;
+    }
+
+    public static async forget(key: string): Promise<boolean> {
+        const result = await forget_database(key);
+        return result.success && result.data;
+    }
+
     public static async attach(key: string): Promise<Database | null> {
         return (await Database.list_databases()).includes(key)
             ? new Database(key)
@@ -69,6 +144,12 @@ export class Database {
         return res.success ? res.data : [];
     }
 
+    public async save_as(path: string): Promise<boolean> {
+        this.check();
+        const res = await save_as(this.key, path);
+        return res.success;
+    }
+
     public async close(): Promise<boolean> {
         this.check();
         const res = await close_database(this.key);
@@ -80,10 +161,46 @@ export class Database {
         }
     }
 
+    public async subscribe(
+        onChange: (change: ChangeRecord) => void,
+        filter?: Omit<ChangeFilter, "database">
+    ): Promise<(() => Promise<boolean>) | null> {
+        this.check();
+        const result = await subscribe(
+            { ...filter, database: this.key },
+            onChange
+        );
+        return result.success
+            ? async () => (await unsubscribe(result.data)).success
+            : null;
+    }
+
     public collection<T extends object = any>(name: string): Collection<T> {
         this.check();
         return new Collection<T>(this, name);
     }
+
+    public async transaction<R>(
+        operation: (db: Database) => Promise<R>
+    ): Promise<R> {
+        this.check();
+        const begun = await begin_transaction(this.key);
+        if (!begun.success) {
+            throw Error(`Failed to begin transaction: ${begun.message}`);
+        }
+
+        try {
+            const result = await operation(new Database(this.key, begun.data));
+            const committed = await commit_transaction(begun.data);
+            if (!committed.success) {
+                throw Error(`Failed to commit transaction: ${committed.message}`);
+            }
+            return result;
+        } catch (e) {
+            await abort_transaction(begun.data);
+            throw e;
+        }
+    }
 }
 
 export class Collection<T extends object = any> {
@@ -101,6 +218,10 @@ export class Collection<T extends object = any> {
         return this._database.key;
     }
 
+    public get transaction() {
+        return this._database.transactionId;
+    }
+
     public check() {
         this._database.check();
     }
@@ -111,51 +232,261 @@ export class Collection<T extends object = any> {
         return objects.map(
             (v) =>
                 (Object.keys(v).includes("_id")
-                    ? Object.keys(v._id).includes("$oid")
-                        ? { _id: v._id["$oid"], ...omit(v, "_id") }
-                        : { _id: null, ...omit(v, "_id") }
+                    ? { _id: normalizeId(v._id), ...omit(v, "_id") }
                     : v) as Document<T>
         );
     }
 
     public async find<Query extends object = PartialDeep<T>>(
         query: Query,
-        sort?: any
+        sort?: any,
+        options?: FindOptions
     ): Promise<Document<T>[]> {
         this.check();
-        const result = await find_many(this.database, this.name, query, sort);
+        const result = await find_many(
+            this.database,
+            this.name,
+            query,
+            sort,
+            options,
+            this.transaction
+        );
         return result.success ? this.makeDocuments<T>(...result.data) : [];
     }
 
     public async find_one<Query extends object = PartialDeep<T>>(
-        query: Query
+        query: Query,
+        sort?: any,
+        options?: Omit<FindOptions, "limit">
     ): Promise<Document<T> | null> {
         this.check();
-        const result = await find_one(this.database, this.name, query);
-        return result.success ? this.makeDocuments<T>(result.data)[0] : null;
+        const result = await find_one(
+            this.database,
+            this.name,
+            query,
+            sort,
+            options,
+            this.transaction
+        );
+        return result.success && result.data
+            ? this.makeDocuments<T>(result.data)[0]
+            : null;
     }
 
-    public async all(sort?: any): Promise<Document<T>[]> {
+    public async all(
+        sort?: any,
+        options?: FindOptions
+    ): Promise<Document<T>[]> {
         this.check();
-        const result = await find_all(this.database, this.name, sort);
+        const result = await find_all(
+            this.database,
+            this.name,
+            sort,
+            options,
+            this.transaction
+        );
         return result.success ? this.makeDocuments<T>(...result.data) : [];
     }
 
-    public async get(id: string): Promise<Document<T> | null> {
-        return await this.find_one({ _id: { $oid: id } });
+    public async cursor<Query extends object = PartialDeep<T>>(
+        query: Query,
+        sort?: any,
+        options?: FindOptions
+    ): Promise<Cursor<T> | null> {
+        this.check();
+        const result = await open_cursor(
+            this.database,
+            this.name,
+            query,
+            sort,
+            options,
+            this.transaction
+        );
+        return result.success
+            ? new Cursor<T>(result.data, (documents) =>
+                  this.makeDocuments<T>(...documents)
+              )
+            : null;
+    }
+
+    public async count<Query extends object = PartialDeep<T>>(
+        query: Query
+    ): Promise<number | null> {
+        this.check();
+        const result = await count(
+            this.database,
+            this.name,
+            query,
+            this.transaction
+        );
+        return result.success ? result.data : null;
     }
 
-    public async insert(...documents: T[]): Promise<number | null> {
+    public async estimated_count(): Promise<number | null> {
         this.check();
-        const result = await insert(this.database, this.name, documents);
+        const result = await estimated_count(
+            this.database,
+            this.name,
+            this.transaction
+        );
         return result.success ? result.data : null;
     }
 
+    public async distinct<
+        Value = any,
+        Query extends object = PartialDeep<T>
+    >(field: string, query: Query): Promise<Value[]> {
+        this.check();
+        const result = await distinct<Value>(
+            this.database,
+            this.name,
+            field,
+            query,
+            this.transaction
+        );
+        return result.success ? result.data : [];
+    }
+
+    public async aggregate<Output extends object = any>(
+        ...pipeline: object[]
+    ): Promise<Output[]> {
+        this.check();
+        const result = await aggregate<Output>(
+            this.database,
+            this.name,
+            pipeline,
+            this.transaction
+        );
+        return result.success ? result.data : [];
+    }
+
+    public async create_index(index: IndexDefinition): Promise<string | null> {
+        this.check();
+        const result = await create_index(
+            this.database,
+            this.name,
+            index,
+            this.transaction
+        );
+        return result.success ? result.data : null;
+    }
+
+    public async drop_index(name: string): Promise<boolean> {
+        this.check();
+        const result = await drop_index(
+            this.database,
+            this.name,
+            name,
+            this.transaction
+        );
+        return result.success;
+    }
+
+    public async drop(): Promise<boolean> {
+        this.check();
+        const result = await drop_collection(
+            this.database,
+            this.name,
+            this.transaction
+        );
+        return result.success;
+    }
+
+    public async subscribe(
+        onChange: (change: ChangeRecord<Document<T>>) => void,
+        filter?: Omit<ChangeFilter, "database" | "collection">
+    ): Promise<(() => Promise<boolean>) | null> {
+        this.check();
+        const result = await subscribe<Document<T>>(
+            { ...filter, database: this.database, collection: this.name },
+            onChange
+        );
+        return result.success
+            ? async () => (await unsubscribe(result.data)).success
+            : null;
+    }
+
+    public async watch<Query extends object = PartialDeep<T>>(
+        query: Query,
+        onUpdate: (update: LiveQueryUpdate<Document<T>>) => void,
+        sort?: any,
+        options?: FindOptions & { mode?: LiveQueryMode }
+    ): Promise<{
+        documents: Document<T>[];
+        stop: () => Promise<boolean>;
+    } | null> {
+        this.check();
+        const result = await watch_query<Document<T>>(
+            this.database,
+            this.name,
+            query,
+            (update) =>
+                onUpdate(
+                    update.type === "results"
+                        ? {
+                              type: "results",
+                              documents: this.makeDocuments<T>(
+                                  ...update.documents
+                              ),
+                          }
+                        : update.type === "diff"
+                        ? {
+                              type: "diff",
+                              added: this.makeDocuments<T>(...update.added),
+                              removed: update.removed,
+                              changed: this.makeDocuments<T>(
+                                  ...update.changed
+                              ),
+                          }
+                        : update
+                ),
+            sort,
+            options,
+            options?.mode
+        );
+        if (!result.success) {
+            return null;
+        }
+        const id = result.data.id;
+        return {
+            documents: this.makeDocuments<T>(...result.data.documents),
+            stop: async () => (await unwatch(id)).success,
+        };
+    }
+
+    public async indexes(): Promise<IndexDefinition[]> {
+        this.check();
+        const result = await list_indexes(this.database, this.name);
+        return result.success ? result.data : [];
+    }
+
+    public async get(id: DocumentId): Promise<Document<T> | null> {
+        return await this.find_one({ _id: id });
+    }
+
+    public async insert(...documents: T[]): Promise<DocumentId[] | null> {
+        this.check();
+        const result = await insert(
+            this.database,
+            this.name,
+            documents,
+            this.transaction
+        );
+        return result.success
+            ? result.data.map((id) => normalizeId(id) as DocumentId)
+            : null;
+    }
+
     public async delete_many<Query extends object = PartialDeep<T>>(
         query: Query
     ): Promise<number | null> {
         this.check();
-        const result = await delete_many(this.database, this.name, query);
+        const result = await delete_many(
+            this.database,
+            this.name,
+            query,
+            this.transaction
+        );
         return result.success ? result.data : null;
     }
 
@@ -163,13 +494,22 @@ export class Collection<T extends object = any> {
         query: Query
     ): Promise<number | null> {
         this.check();
-        const result = await delete_one(this.database, this.name, query);
+        const result = await delete_one(
+            this.database,
+            this.name,
+            query,
+            this.transaction
+        );
         return result.success ? result.data : null;
     }
 
     public async delete_all(): Promise<number | null> {
         this.check();
-        const result = await delete_all(this.database, this.name);
+        const result = await delete_all(
+            this.database,
+            this.name,
+            this.transaction
+        );
         return result.success ? result.data : null;
     }
 
@@ -184,7 +524,8 @@ export class Collection<T extends object = any> {
             this.name,
             query,
             update,
-            upsert
+            upsert,
+            this.transaction
         );
         return result.success ? result.data : null;
     }
@@ -200,11 +541,90 @@ export class Collection<T extends object = any> {
             this.name,
             query,
             update,
-            upsert
+            upsert,
+            this.transaction
+        );
+        return result.success ? result.data : null;
+    }
+
+    public async replace_one<Query extends object = PartialDeep<T>>(
+        query: Query,
+        replacement: T | Document<T>,
+        upsert?: boolean
+    ): Promise<number | null> {
+        this.check();
+        const result = await replace_one(
+            this.database,
+            this.name,
+            withWireId(query),
+            replacement,
+            upsert,
+            this.transaction
         );
         return result.success ? result.data : null;
     }
 
+    public async find_one_and_update<Query extends object = PartialDeep<T>>(
+        query: Query,
+        update: object,
+        sort?: any,
+        options?: FindAndModifyOptions
+    ): Promise<Document<T> | null> {
+        this.check();
+        const result = await find_one_and_update(
+            this.database,
+            this.name,
+            query,
+            update,
+            sort,
+            options,
+            this.transaction
+        );
+        return result.success && result.data
+            ? this.makeDocuments<T>(result.data)[0]
+            : null;
+    }
+
+    public async find_one_and_replace<Query extends object = PartialDeep<T>>(
+        query: Query,
+        replacement: T | Document<T>,
+        sort?: any,
+        options?: FindAndModifyOptions
+    ): Promise<Document<T> | null> {
+        this.check();
+        const result = await find_one_and_replace(
+            this.database,
+            this.name,
+            withWireId(query),
+            replacement,
+            sort,
+            options,
+            this.transaction
+        );
+        return result.success && result.data
+            ? this.makeDocuments<T>(result.data)[0]
+            : null;
+    }
+
+    public async find_one_and_delete<Query extends object = PartialDeep<T>>(
+        query: Query,
+        sort?: any,
+        options?: Pick<FindAndModifyOptions, "projection">
+    ): Promise<Document<T> | null> {
+        this.check();
+        const result = await find_one_and_delete(
+            this.database,
+            this.name,
+            query,
+            sort,
+            options,
+            this.transaction
+        );
+        return result.success && result.data
+            ? this.makeDocuments<T>(result.data)[0]
+            : null;
+    }
+
     public async update_all(
         update: object,
         upsert?: boolean
@@ -214,8 +634,47 @@ export class Collection<T extends object = any> {
             this.database,
             this.name,
             update,
-            upsert
+            upsert,
+            this.transaction
         );
         return result.success ? result.data : null;
     }
 }
+
+export class Cursor<T extends object = any> {
+    private _exhausted: boolean = false;
+
+    constructor(
+        private _id: string,
+        private _convert: (documents: object[]) => Document<T>[]
+    ) {}
+
+    public get id() {
+        return this._id;
+    }
+
+    public get exhausted() {
+        return this._exhausted;
+    }
+
+    public async next(size: number = 100): Promise<Document<T>[]> {
+        if (this.exhausted) {
+            return [];
+        }
+        const result = await next_batch(this.id, size);
+        if (!result.success) {
+            this._exhausted = true;
+            return [];
+        }
+        this._exhausted = result.data.exhausted;
+        return this._convert(result.data.documents);
+    }
+
+    public async close(): Promise<boolean> {
+        if (this.exhausted) {
+            return true;
+        }
+        this._exhausted = true;
+        return (await close_cursor(this.id)).success;
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use polodb_core::bson::{Bson, Document};
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Channel;
+use uuid::Uuid;
+
+use crate::scope::Handle;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+    Delete,
+    Drop,
+}
+
+/// A write that has been applied to a collection. `documents` holds the new state of the affected
+/// documents for inserts and updates.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChangeRecord {
+    pub database: String,
+    pub collection: String,
+    pub operation: ChangeOperation,
+    pub ids: Vec<Bson>,
+    #[serde(default)]
+    pub documents: Option<Vec<Document>>,
+}
+
+impl ChangeRecord {
+    pub fn new<D: AsRef<str>, C: AsRef<str>>(
+        database: D,
+        collection: C,
+        operation: ChangeOperation,
+        ids: Vec<Bson>,
+        documents: Option<Vec<Document>>,
+    ) -> Self {
+        ChangeRecord {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            operation,
+            ids,
+            documents,
+        }
+    }
+
+    /// Returns a copy of this record without the affected documents.
+    pub fn summary(&self) -> Self {
+        ChangeRecord {
+            documents: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// Selects which change records a subscriber receives. Unset fields match everything.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeFilter {
+    #[serde(default)]
+    pub database: Option<String>,
+    #[serde(default)]
+    pub collection: Option<String>,
+    #[serde(default)]
+    pub operations: Option<Vec<ChangeOperation>>,
+    /// Whether to deliver the affected documents along with their ids.
+    #[serde(default)]
+    pub documents: bool,
+}
+
+/// How much of a write the subscribers to its collection want to receive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Interest {
+    None,
+    Ids,
+    Documents,
+}
+
+impl ChangeFilter {
+    /// Whether this filter selects changes to `database`/`collection`, whatever their operation.
+    pub fn covers(&self, database: &str, collection: &str) -> bool {
+        self.database.as_ref().map_or(true, |d| d == database)
+            && self.collection.as_ref().map_or(true, |c| c == collection)
+    }
+
+    pub fn matches(&self, record: &ChangeRecord) -> bool {
+        self.database
+            .as_ref()
+            .map_or(true, |d| *d == record.database)
+            && self
+                .collection
+                .as_ref()
+                .map_or(true, |c| *c == record.collection)
+            && self
+                .operations
+                .as_ref()
+                .map_or(true, |o| o.contains(&record.operation))
+    }
+}
+
+#[derive(Clone)]
+pub enum ChangeSink {
+    Channel(Channel<ChangeRecord>),
+    Callback(Arc<dyn Fn(&ChangeRecord) + Send + Sync>),
+}
+
+impl ChangeSink {
+    fn send(&self, record: ChangeRecord) -> bool {
+        match self {
+            ChangeSink::Channel(channel) => channel.send(record).is_ok(),
+            ChangeSink::Callback(callback) => {
+                callback(&record);
+                true
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Subscription {
+    filter: ChangeFilter,
+    owner: Option<String>,
+    sink: ChangeSink,
+}
+
+/// Registry of change subscribers, fed by the daemon's change stream.
+#[derive(Clone, Default)]
+pub struct ChangeHub {
+    subscriptions: Arc<Mutex<HashMap<Uuid, Subscription>>>,
+}
+
+impl ChangeHub {
+    pub fn subscribe(
+        &self,
+        filter: ChangeFilter,
+        owner: Option<String>,
+        sink: ChangeSink,
+    ) -> Result<Uuid, crate::Error> {
+        let id = Uuid::new_v4();
+        self.subscriptions
+            .lock()
+            .map_err(|_| crate::Error::sync("subscriptions"))?
+            .insert(
+                id,
+                Subscription {
+                    filter,
+                    owner,
+                    sink,
+                },
+            );
+        Ok(id)
+    }
+
+    pub fn unsubscribe(&self, id: Uuid) -> Result<(), crate::Error> {
+        match self
+            .subscriptions
+            .lock()
+            .map_err(|_| crate::Error::sync("subscriptions"))?
+            .remove(&id)
+        {
+            Some(_) => Ok(()),
+            None => Err(crate::Error::UnknownSubscription {
+                subscription: id.to_string(),
+            }),
+        }
+    }
+
+    /// Who made the subscription `id`, and what it listens to.
+    pub fn handle(&self, id: &Uuid) -> Option<Handle> {
+        let subscriptions = self.subscriptions.lock().ok()?;
+        let subscription = subscriptions.get(id)?;
+        Some(Handle {
+            owner: subscription.owner.clone(),
+            database: subscription.filter.database.clone(),
+            collection: subscription.filter.collection.clone(),
+        })
+    }
+
+    /// Drops every subscription made by `owner`, returning how many were removed.
+    pub fn release<O: AsRef<str>>(&self, owner: O) -> usize {
+        match self.subscriptions.lock() {
+            Ok(mut subscriptions) => {
+                let before = subscriptions.len();
+                subscriptions.retain(|_, s| s.owner.as_deref() != Some(owner.as_ref()));
+                before - subscriptions.len()
+            }
+            Err(_) => 0,
+        }
+    }
+
+    /// What the subscribers whose filters cover `database`/`collection` want to receive, so writes
+    /// nobody listens to skip collecting change records.
+    pub fn interest(&self, database: &str, collection: &str) -> Interest {
+        match self.subscriptions.lock() {
+            Ok(subscriptions) => subscriptions
+                .values()
+                .filter(|s| s.filter.covers(database, collection))
+                .map(|s| match s.filter.documents {
+                    true => Interest::Documents,
+                    false => Interest::Ids,
+                })
+                .max()
+                .unwrap_or(Interest::None),
+            Err(_) => Interest::Documents,
+        }
+    }
+
+    /// Delivers `record` to every matching subscriber, dropping those whose sink has gone away.
+    pub fn dispatch(&self, record: &ChangeRecord) {
+        let targets = match self.subscriptions.lock() {
+            Ok(subscriptions) => subscriptions
+                .iter()
+                .filter(|(_, s)| s.filter.matches(record))
+                .map(|(id, s)| (*id, s.clone()))
+                .collect::<Vec<(Uuid, Subscription)>>(),
+            Err(_) => return,
+        };
+
+        let closed = targets
+            .into_iter()
+            .filter(|(_, s)| {
+                !s.sink.send(if s.filter.documents {
+                    record.clone()
+                } else {
+                    record.summary()
+                })
+            })
+            .map(|(id, _)| id)
+            .collect::<Vec<Uuid>>();
+
+        if !closed.is_empty() {
+            if let Ok(mut subscriptions) = self.subscriptions.lock() {
+                for id in closed {
+                    subscriptions.remove(&id);
+                }
+            }
+        }
+    }
+}
@@ -1,169 +1,889 @@
+use polodb_core::bson::{Bson, Document};
+use serde::Deserialize;
 use serde_json::Value;
-use tauri::{Manager, Runtime};
+use tauri::{
+    ipc::{Channel, CommandArg, CommandItem, InvokeError},
+    Runtime,
+};
+use uuid::Uuid;
 
-use crate::PolodbExt;
+use crate::{
+    changes::{ChangeFilter, ChangeRecord},
+    daemon::{
+        CursorBatch, DaemonStatus, FindAndModifyOptions, FindOptions, IndexDefinition,
+        RequestOptions, ReturnDocument, SerializedDatabase,
+    },
+    error::to_argument,
+    live::{LiveQuery, LiveQueryMode, LiveQueryOptions, LiveQueryUpdate},
+    scope::{Access, Scopes},
+    PolodbExt,
+};
+
+/// Fails unless the window or webview labelled `label` opened the handle `id` and may still read
+/// what it reaches. Unknown ids are left to the command, which reports them.
+fn check_handle<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    label: &str,
+    scopes: &Scopes,
+    id: Uuid,
+) -> Result<(), crate::Error> {
+    match app.polodb().handle(id) {
+        Some(handle) => handle.check(label, scopes),
+        None => Ok(()),
+    }
+}
+
+/// The optional `request` argument of a command, tagged with the label of the calling webview so
+/// its requests can be cancelled when it navigates away, and with whether its window is focused.
+pub struct Request(pub RequestOptions);
+
+impl<'a, R: Runtime> CommandArg<'a, R> for Request {
+    fn from_command(command: CommandItem<'a, R>) -> Result<Self, InvokeError> {
+        let webview = command.message.webview_ref();
+        let owner = webview.label().to_string();
+        let focused = webview.window().is_focused().unwrap_or(false);
+        let options = Option::<RequestOptions>::deserialize(CommandItem {
+            plugin: command.plugin,
+            name: command.name,
+            key: "request",
+            message: command.message,
+            acl: command.acl,
+        })
+        .map_err(InvokeError::from_error)?
+        .unwrap_or_default();
+        Ok(Request(RequestOptions {
+            owner: Some(owner),
+            focused,
+            ..options
+        }))
+    }
+}
+
+fn to_options_document(
+    argument: &str,
+    value: Option<Value>,
+) -> Result<Option<Document>, crate::Error> {
+    value.map(|v| to_argument(argument, &v)).transpose()
+}
+
+fn find_options(
+    sort: Option<Value>,
+    skip: Option<u64>,
+    limit: Option<u64>,
+    projection: Option<Value>,
+) -> Result<FindOptions, crate::Error> {
+    Ok(FindOptions {
+        sort: to_options_document("sort", sort)?,
+        skip,
+        limit,
+        projection: to_options_document("projection", projection)?,
+    })
+}
+
+fn find_and_modify_options(
+    sort: Option<Value>,
+    projection: Option<Value>,
+    upsert: Option<bool>,
+    return_document: Option<ReturnDocument>,
+) -> Result<FindAndModifyOptions, crate::Error> {
+    Ok(FindAndModifyOptions {
+        sort: to_options_document("sort", sort)?,
+        projection: to_options_document("projection", projection)?,
+        upsert: upsert.unwrap_or(false),
+        return_document: return_document.unwrap_or_default(),
+    })
+}
 
 #[tauri::command]
 pub async fn list_databases<R: Runtime>(
     app: tauri::AppHandle<R>,
+    scopes: Scopes,
 ) -> Result<Vec<String>, crate::Error> {
-    app.polodb().list_databases().await
+    Ok(app
+        .polodb()
+        .list_databases()
+        .await?
+        .into_iter()
+        .filter(|database| scopes.reveals(database, None))
+        .collect())
 }
 
 #[tauri::command]
 pub async fn list_collections<R: Runtime>(
     app: tauri::AppHandle<R>,
-    database: String
+    database: String,
+    scopes: Scopes,
 ) -> Result<Vec<String>, crate::Error> {
-    app.polodb().list_collections(database).await
+    Ok(app
+        .polodb()
+        .list_collections(&database)
+        .await?
+        .into_iter()
+        .filter(|collection| scopes.reveals(&database, Some(collection)))
+        .collect())
 }
 
 #[tauri::command]
 pub async fn open_database<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
     key: String,
     path: String,
+    read_only: Option<bool>,
+    scopes: Scopes,
 ) -> Result<String, crate::Error> {
-    if let Ok(buf) = app.path().parse(path) {
-        app.polodb()
-            .open_database(key, buf.to_str().unwrap().to_string())
-            .await
-    } else {
-        Err(crate::Error::Io("Invalid path".to_string()))
+    let access = match read_only {
+        Some(true) => Access::Read,
+        _ => Access::Write,
+    };
+    scopes.check(Some(&key), None, access)?;
+    let polodb = app.polodb().with_request(request.0);
+    let path =
+        scopes.resolve_database_path(&app, &polodb.scope, polodb.directory.as_deref(), &path)?;
+    match (path.to_str(), read_only.unwrap_or(false)) {
+        (Some(path), false) => polodb.open_database(key, path).await,
+        (Some(path), true) => polodb.open_database_read_only(key, path).await,
+        (None, _) => Err(crate::Error::io("Invalid path")),
+    }
+}
+
+#[tauri::command]
+pub async fn open_memory_database<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    key: String,
+) -> Result<String, crate::Error> {
+    scopes.check(Some(&key), None, Access::Write)?;
+    app.polodb()
+        .with_request(request.0)
+        .open_memory_database(key)
+        .await
+}
+
+#[tauri::command]
+pub async fn save_as<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    key: String,
+    path: String,
+    scopes: Scopes,
+) -> Result<String, crate::Error> {
+    scopes.check(Some(&key), None, Access::Write)?;
+    let polodb = app.polodb().with_request(request.0);
+    let path =
+        scopes.resolve_database_path(&app, &polodb.scope, polodb.directory.as_deref(), &path)?;
+    match path.to_str() {
+        Some(path) => polodb.save_as(key, path).await,
+        None => Err(crate::Error::io("Invalid path")),
     }
 }
 
 #[tauri::command]
 pub async fn close_database<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     key: String,
 ) -> Result<String, crate::Error> {
-    app.polodb().close_database(key).await
+    scopes.check(Some(&key), None, Access::Write)?;
+    app.polodb()
+        .with_request(request.0)
+        .close_database(key)
+        .await
+}
+
+#[tauri::command]
+pub async fn list_known_databases<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    scopes: Scopes,
+) -> Result<Vec<SerializedDatabase>, crate::Error> {
+    Ok(app
+        .polodb()
+        .list_known_databases()
+        .await?
+        .into_iter()
+        .filter(|database| scopes.reveals(&database.key, None))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn forget_database<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    scopes: Scopes,
+    key: String,
+) -> Result<bool, crate::Error> {
+    scopes.check(Some(&key), None, Access::Write)?;
+    app.polodb().forget_database(key).await
+}
+
+#[tauri::command]
+pub async fn daemon_status<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    scopes: Scopes,
+) -> Result<DaemonStatus, crate::Error> {
+    let mut status = app.polodb().daemon_status();
+    status
+        .databases
+        .retain(|database| scopes.reveals(&database.database, None));
+    status
+        .failed
+        .retain(|failure| scopes.reveals(&failure.database, None));
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn cancel<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    webview: tauri::Webview<R>,
+    scopes: Scopes,
+    request: Uuid,
+) -> Result<bool, crate::Error> {
+    check_handle(&app, webview.label(), &scopes, request)?;
+    Ok(app.polodb().cancel(request))
+}
+
+#[tauri::command]
+pub async fn begin_transaction<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    window: tauri::Window<R>,
+    scopes: Scopes,
+    database: String,
+) -> Result<Uuid, crate::Error> {
+    scopes.check(Some(&database), None, Access::Read)?;
+    app.polodb()
+        .begin_transaction(database, Some(window.label().to_string()))
+        .await
+}
+
+#[tauri::command]
+pub async fn commit_transaction<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    window: tauri::Window<R>,
+    scopes: Scopes,
+    transaction: Uuid,
+) -> Result<(), crate::Error> {
+    check_handle(&app, window.label(), &scopes, transaction)?;
+    app.polodb().commit_transaction(transaction).await
+}
+
+#[tauri::command]
+pub async fn abort_transaction<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    window: tauri::Window<R>,
+    scopes: Scopes,
+    transaction: Uuid,
+) -> Result<(), crate::Error> {
+    check_handle(&app, window.label(), &scopes, transaction)?;
+    app.polodb().abort_transaction(transaction).await
 }
 
 #[tauri::command]
 pub async fn insert<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     documents: Vec<Value>,
-) -> Result<Vec<usize>, crate::Error> {
-    app.polodb().insert(database, collection, documents).await
+    transaction: Option<Uuid>,
+) -> Result<Vec<Bson>, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .insert(database, collection, documents)
+        .await
 }
 
 #[tauri::command]
 pub async fn insert_one<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     document: Value,
-) -> Result<Vec<usize>, crate::Error> {
+    transaction: Option<Uuid>,
+) -> Result<Bson, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
     app.polodb()
-        .insert(database, collection, vec![document])
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .insert_one(database, collection, document)
         .await
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn find<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     query: Value,
     sort: Option<Value>,
+    skip: Option<u64>,
+    limit: Option<u64>,
+    projection: Option<Value>,
+    transaction: Option<Uuid>,
 ) -> Result<Vec<Value>, crate::Error> {
-    match sort {
-        Some(sorting) => {
-            app.polodb()
-                .find_sorted(database, collection, query, sorting)
-                .await
-        }
-        None => app.polodb().find(database, collection, query).await,
-    }
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .find_with_options(
+            database,
+            collection,
+            query,
+            find_options(sort, skip, limit, projection)?,
+        )
+        .await
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn find_all<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     sort: Option<Value>,
+    skip: Option<u64>,
+    limit: Option<u64>,
+    projection: Option<Value>,
+    transaction: Option<Uuid>,
 ) -> Result<Vec<Value>, crate::Error> {
-    match sort {
-        Some(sorting) => app.polodb().all_sorted(database, collection, sorting).await,
-        None => app.polodb().all(database, collection).await,
-    }
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .all_with_options(
+            database,
+            collection,
+            find_options(sort, skip, limit, projection)?,
+        )
+        .await
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn open_cursor<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    query: Value,
+    sort: Option<Value>,
+    skip: Option<u64>,
+    limit: Option<u64>,
+    projection: Option<Value>,
+    transaction: Option<Uuid>,
+) -> Result<Uuid, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .open_cursor(
+            database,
+            collection,
+            query,
+            find_options(sort, skip, limit, projection)?,
+        )
+        .await
+}
+
+#[tauri::command]
+pub async fn next_batch<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    webview: tauri::Webview<R>,
+    request: Request,
+    scopes: Scopes,
+    cursor: Uuid,
+    size: usize,
+) -> Result<CursorBatch<Value>, crate::Error> {
+    check_handle(&app, webview.label(), &scopes, cursor)?;
+    app.polodb()
+        .with_request(request.0)
+        .next_batch(cursor, size)
+        .await
+}
+
+#[tauri::command]
+pub async fn close_cursor<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    webview: tauri::Webview<R>,
+    scopes: Scopes,
+    cursor: Uuid,
+) -> Result<(), crate::Error> {
+    check_handle(&app, webview.label(), &scopes, cursor)?;
+    app.polodb().close_cursor(cursor).await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn find_one<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    query: Value,
+    sort: Option<Value>,
+    skip: Option<u64>,
+    projection: Option<Value>,
+    transaction: Option<Uuid>,
+) -> Result<Option<Value>, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .find_one_with_options(
+            database,
+            collection,
+            query,
+            find_options(sort, skip, None, projection)?,
+        )
+        .await
+}
+
+#[tauri::command]
+pub async fn count<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    query: Value,
+    transaction: Option<Uuid>,
+) -> Result<u64, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .count(database, collection, query)
+        .await
+}
+
+#[tauri::command]
+pub async fn estimated_count<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    transaction: Option<Uuid>,
+) -> Result<u64, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .estimated_count(database, collection)
+        .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn distinct<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
+    field: String,
     query: Value,
-) -> Result<Value, crate::Error> {
-    app.polodb().find_one(database, collection, query).await
+    transaction: Option<Uuid>,
+) -> Result<Vec<Bson>, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .distinct(database, collection, field, query)
+        .await
+}
+
+#[tauri::command]
+pub async fn aggregate<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    pipeline: Vec<Value>,
+    transaction: Option<Uuid>,
+) -> Result<Vec<Value>, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .aggregate(database, collection, pipeline)
+        .await
+}
+
+#[tauri::command]
+pub async fn create_index<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    index: IndexDefinition,
+    transaction: Option<Uuid>,
+) -> Result<String, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .create_index(database, collection, index)
+        .await
+}
+
+#[tauri::command]
+pub async fn drop_index<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    name: String,
+    transaction: Option<Uuid>,
+) -> Result<(), crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .drop_index(database, collection, name)
+        .await
+}
+
+#[tauri::command]
+pub async fn list_indexes<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+) -> Result<Vec<IndexDefinition>, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .with_request(request.0)
+        .list_indexes(database, collection)
+        .await
 }
 
 #[tauri::command]
 pub async fn delete<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     query: Value,
+    transaction: Option<Uuid>,
 ) -> Result<u64, crate::Error> {
-    app.polodb().delete(database, collection, query).await
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .delete(database, collection, query)
+        .await
 }
 
 #[tauri::command]
 pub async fn delete_one<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     query: Value,
+    transaction: Option<Uuid>,
 ) -> Result<u64, crate::Error> {
-    app.polodb().delete_one(database, collection, query).await
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .delete_one(database, collection, query)
+        .await
 }
 
 #[tauri::command]
 pub async fn delete_all<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
-    collection: String
+    collection: String,
+    transaction: Option<Uuid>,
 ) -> Result<u64, crate::Error> {
-    app.polodb().delete_all(database, collection).await
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .delete_all(database, collection)
+        .await
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn update<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     query: Value,
     update: Value,
-    upsert: bool
+    upsert: bool,
+    transaction: Option<Uuid>,
 ) -> Result<u64, crate::Error> {
-    app.polodb().update(database, collection, query, update, upsert).await
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .update(database, collection, query, update, upsert)
+        .await
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn update_one<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     query: Value,
     update: Value,
-    upsert: bool
+    upsert: bool,
+    transaction: Option<Uuid>,
 ) -> Result<u64, crate::Error> {
-    app.polodb().update_one(database, collection, query, update, upsert).await
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .update_one(database, collection, query, update, upsert)
+        .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn replace_one<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    query: Value,
+    replacement: Value,
+    upsert: bool,
+    transaction: Option<Uuid>,
+) -> Result<u64, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .replace_one(database, collection, query, replacement, upsert)
+        .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_one_and_update<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    query: Value,
+    update: Value,
+    sort: Option<Value>,
+    projection: Option<Value>,
+    upsert: Option<bool>,
+    return_document: Option<ReturnDocument>,
+    transaction: Option<Uuid>,
+) -> Result<Option<Value>, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .find_one_and_update(
+            database,
+            collection,
+            query,
+            update,
+            find_and_modify_options(sort, projection, upsert, return_document)?,
+        )
+        .await
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_one_and_replace<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    query: Value,
+    replacement: Value,
+    sort: Option<Value>,
+    projection: Option<Value>,
+    upsert: Option<bool>,
+    return_document: Option<ReturnDocument>,
+    transaction: Option<Uuid>,
+) -> Result<Option<Value>, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .find_one_and_replace(
+            database,
+            collection,
+            query,
+            replacement,
+            find_and_modify_options(sort, projection, upsert, return_document)?,
+        )
+        .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_one_and_delete<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    query: Value,
+    sort: Option<Value>,
+    projection: Option<Value>,
+    transaction: Option<Uuid>,
+) -> Result<Option<Value>, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .find_one_and_delete(
+            database,
+            collection,
+            query,
+            find_and_modify_options(sort, projection, None, None)?,
+        )
+        .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn update_all<R: Runtime>(
     app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
     database: String,
     collection: String,
     update: Value,
-    upsert: bool
+    upsert: bool,
+    transaction: Option<Uuid>,
 ) -> Result<u64, crate::Error> {
-    app.polodb().update_all(database, collection, update, upsert).await
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .update_all(database, collection, update, upsert)
+        .await
+}
+
+#[tauri::command]
+pub async fn drop_collection<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    request: Request,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    transaction: Option<Uuid>,
+) -> Result<(), crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Write)?;
+    app.polodb()
+        .with_transaction(transaction)
+        .with_request(request.0)
+        .drop_collection(database, collection)
+        .await
+}
+
+#[tauri::command]
+pub async fn subscribe<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    window: tauri::Window<R>,
+    scopes: Scopes,
+    filter: Option<ChangeFilter>,
+    channel: Channel<ChangeRecord>,
+) -> Result<Uuid, crate::Error> {
+    let filter = filter.unwrap_or_default();
+    scopes.check(
+        filter.database.as_deref(),
+        filter.collection.as_deref(),
+        Access::Read,
+    )?;
+    app.polodb()
+        .subscribe_channel(filter, Some(window.label().to_string()), channel)
+}
+
+#[tauri::command]
+pub async fn unsubscribe<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    window: tauri::Window<R>,
+    scopes: Scopes,
+    subscription: Uuid,
+) -> Result<(), crate::Error> {
+    check_handle(&app, window.label(), &scopes, subscription)?;
+    app.polodb().unsubscribe(subscription)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn watch_query<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    window: tauri::Window<R>,
+    scopes: Scopes,
+    database: String,
+    collection: String,
+    query: Value,
+    sort: Option<Value>,
+    skip: Option<u64>,
+    limit: Option<u64>,
+    projection: Option<Value>,
+    mode: Option<LiveQueryMode>,
+    channel: Channel<LiveQueryUpdate>,
+) -> Result<LiveQuery, crate::Error> {
+    scopes.check(Some(&database), Some(&collection), Access::Read)?;
+    app.polodb()
+        .watch_query(
+            database,
+            collection,
+            query,
+            LiveQueryOptions {
+                find: find_options(sort, skip, limit, projection)?,
+                mode: mode.unwrap_or_default(),
+            },
+            Some(window.label().to_string()),
+            move |update| channel.send(update).is_ok(),
+        )
+        .await
+}
+
+#[tauri::command]
+pub async fn unwatch<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    window: tauri::Window<R>,
+    scopes: Scopes,
+    query: Uuid,
+) -> Result<(), crate::Error> {
+    check_handle(&app, window.label(), &scopes, query)?;
+    app.polodb().unsubscribe(query)
 }
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::utils::config::FsScope;
+
+use crate::daemon::{
+    IndexDefinition, CURSOR_TIMEOUT, MAINTENANCE_INTERVAL, QUEUE_CAPACITY, REQUEST_TIMEOUT,
+    SHUTDOWN_TIMEOUT, TRANSACTION_TIMEOUT,
+};
+
+/// Plugin configuration, read from `plugins.polodb` in `tauri.conf.json`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    /// Directory relative database paths are resolved against, instead of the working directory.
+    /// May start with a path variable such as `$APPDATA`, and is created if missing.
+    #[serde(default)]
+    pub directory: Option<String>,
+    /// Databases opened when the plugin starts.
+    #[serde(default)]
+    pub databases: Vec<DatabaseDeclaration>,
+    /// Indexes ensured every time a matching database is opened.
+    #[serde(default)]
+    pub indexes: Vec<IndexDeclaration>,
+    /// Paths the frontend may open databases at, on top of those granted by permission scopes.
+    #[serde(default)]
+    pub scope: FsScope,
+    #[serde(default)]
+    pub daemon: DaemonOptions,
+}
+
+/// A database opened at startup under `key`. `path` may start with a path variable such as `$APPDATA`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseDeclaration {
+    pub key: String,
+    pub path: String,
+    /// Rejects every write to the database.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// Tuning for the daemon thread. Durations are in seconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DaemonOptions {
+    /// How long a transaction may sit idle before it is rolled back.
+    pub transaction_timeout: u64,
+    /// How long a cursor may sit idle before it is closed.
+    pub cursor_timeout: u64,
+    /// How often idle transactions and cursors are expired.
+    pub maintenance_interval: u64,
+    /// How long to wait on exit for queued commands to finish and databases to close.
+    pub shutdown_timeout: u64,
+    /// How long a call waits for its response before failing, unless it sets its own timeout. Zero
+    /// waits indefinitely.
+    pub request_timeout: u64,
+    /// How many commands may wait for each database. Zero lifts the limit.
+    pub queue_capacity: usize,
+    /// What happens to a command sent while its database's queue is full.
+    pub backpressure: Backpressure,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Backpressure {
+    /// Wait for room, up to the request timeout.
+    #[default]
+    Wait,
+    /// Fail with [`crate::Error::Busy`].
+    Reject,
+}
+
+impl Default for DaemonOptions {
+    fn default() -> Self {
+        DaemonOptions {
+            transaction_timeout: TRANSACTION_TIMEOUT.as_secs(),
+            cursor_timeout: CURSOR_TIMEOUT.as_secs(),
+            maintenance_interval: MAINTENANCE_INTERVAL.as_secs(),
+            shutdown_timeout: SHUTDOWN_TIMEOUT.as_secs(),
+            request_timeout: REQUEST_TIMEOUT.as_secs(),
+            queue_capacity: QUEUE_CAPACITY,
+            backpressure: Backpressure::default(),
+        }
+    }
+}
+
+impl DaemonOptions {
+    pub fn transaction_timeout(&self) -> Duration {
+        Duration::from_secs(self.transaction_timeout)
+    }
+
+    pub fn cursor_timeout(&self) -> Duration {
+        Duration::from_secs(self.cursor_timeout)
+    }
+
+    pub fn maintenance_interval(&self) -> Duration {
+        // A zero interval would spin the maintenance thread.
+        Duration::from_secs(self.maintenance_interval.max(1))
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout)
+    }
+
+    pub fn request_timeout(&self) -> Option<Duration> {
+        match self.request_timeout {
+            0 => None,
+            timeout => Some(Duration::from_secs(timeout)),
+        }
+    }
+}
+
+/// An index to create on `collection`, either in every database or only in `database`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexDeclaration {
+    #[serde(default)]
+    pub database: Option<String>,
+    pub collection: String,
+    #[serde(flatten)]
+    pub index: IndexDefinition,
+}
+
+impl IndexDeclaration {
+    pub fn applies_to<K: AsRef<str>>(&self, database: K) -> bool {
+        match &self.database {
+            Some(key) => key == database.as_ref(),
+            None => true,
+        }
+    }
+}
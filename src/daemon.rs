@@ -1,37 +1,289 @@
 use std::{
-    collections::HashMap,
-    path::Path,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
 };
 
-use polodb_core::{bson::Document, Collection, Database};
+use async_channel::Sender;
+use polodb_core::{
+    action::{Aggregate, Find},
+    bson::{doc, from_document, Bson, Document},
+    options::UpdateOptions,
+    results::{DeleteResult, InsertManyResult, UpdateResult},
+    ClientCursor, Collection, CollectionT, Database, IndexModel, IndexOptions, Transaction,
+    TransactionalCollection,
+};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    changes::{ChangeHub, ChangeOperation, ChangeRecord, Interest},
+    config::{DaemonOptions, IndexDeclaration},
+    projection::Projection,
+};
+use messages::{CountSelect, Modification};
+
+/// Internal collection recording the indexes created through the plugin, since PoloDB cannot list them.
+pub const INDEX_REGISTRY: &str = "__polodb_indexes";
+
+/// File in the app data directory recording the databases to reopen on the next launch.
+pub const KNOWN_DATABASES: &str = "polodb-databases.json";
+
+/// How long a transaction may sit idle before the daemon rolls it back.
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a cursor may sit idle before the daemon closes it.
+pub const CURSOR_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How often the daemon is woken up to expire idle resources.
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long shutdown waits for the workers to finish.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a call waits for its response by default.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many commands may wait for each database by default.
+pub const QUEUE_CAPACITY: usize = 1024;
+
+macro_rules! with_collection {
+    ($coll:expr, $c:ident => $body:expr) => {
+        match $coll {
+            PoloCollection::Direct($c) => $body,
+            PoloCollection::Transactional($c) => $body,
+        }
+    };
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct IndexDefinition {
+    pub keys: Document,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub unique: bool,
+}
+
+impl IndexDefinition {
+    /// The name PoloDB stores the index under: the explicit name, or `<field>_1` for the single indexed field.
+    pub fn resolved_name(&self) -> Result<String, crate::Error> {
+        if let Some(name) = &self.name {
+            return Ok(name.clone());
+        }
+        match self.keys.keys().next() {
+            Some(key) if self.keys.len() == 1 => Ok(format!("{}_1", key.replace('.', "_"))),
+            _ => Err(crate::Error::InvalidIndex {
+                message: "Indexes must cover exactly one field".to_string(),
+            }),
+        }
+    }
+
+    pub fn model(&self) -> IndexModel {
+        IndexModel {
+            keys: self.keys.clone(),
+            options: Some(IndexOptions {
+                name: self.name.clone(),
+                unique: Some(self.unique),
+            }),
+        }
+    }
+}
+
+/// Sorting, paging and projection applied to a find query.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct FindOptions {
+    #[serde(default)]
+    pub sort: Option<Document>,
+    #[serde(default)]
+    pub skip: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+    #[serde(default)]
+    pub projection: Option<Document>,
+}
+
+impl FindOptions {
+    pub fn sorted(sort: Document) -> Self {
+        FindOptions {
+            sort: Some(sort),
+            ..Default::default()
+        }
+    }
+
+    pub fn projection(&self) -> Result<Option<Projection>, crate::Error> {
+        self.projection.as_ref().map(Projection::parse).transpose()
+    }
+}
+
+/// Which version of the document a find-and-modify call returns.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnDocument {
+    #[default]
+    Before,
+    After,
+}
+
+/// Options for the find-and-modify calls, which modify the first document matching their query in
+/// `sort` order. Deletes ignore `upsert` and always return the deleted document.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FindAndModifyOptions {
+    #[serde(default)]
+    pub sort: Option<Document>,
+    #[serde(default)]
+    pub projection: Option<Document>,
+    #[serde(default)]
+    pub upsert: bool,
+    #[serde(default)]
+    pub return_document: ReturnDocument,
+}
+
+/// A page of results read from a cursor. `exhausted` is set once the cursor has no more documents.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CursorBatch<T> {
+    pub documents: Vec<T>,
+    pub exhausted: bool,
+}
+
+/// Per-call settings for a daemon request.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    /// Id the request can be cancelled by.
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// Milliseconds to wait for the response, overriding the daemon's `requestTimeout`. Zero waits
+    /// indefinitely.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Where the request is queued relative to others for the same database. Defaults to `high`
+    /// for reads from the focused window and `normal` otherwise.
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// Label of the webview that sent the request. Its requests are cancelled when it navigates away
+    /// or is closed.
+    #[serde(skip)]
+    pub owner: Option<String>,
+    /// Whether the request came from the focused window.
+    #[serde(skip)]
+    pub focused: bool,
+}
+
+/// How urgently a request runs relative to others queued for the same database.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    /// For bulk background work, which only runs when nothing more urgent is queued.
+    Low,
+}
+
+impl Priority {
+    fn lane(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// Health of the daemon, as reported by [`messages::PoloManager::status`].
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonStatus {
+    /// Seconds since the daemon started.
+    pub uptime: u64,
+    /// How many times a worker recovered from a panic.
+    pub restarts: usize,
+    /// Commands waiting across every worker.
+    pub queued: usize,
+    /// How many commands may wait for each database, or 0 if there is no limit.
+    pub capacity: usize,
+    /// How many commands were turned away with [`crate::Error::Busy`] because their queue was full.
+    pub rejected: usize,
+    /// Whether shutdown has begun.
+    pub closing: bool,
+    pub databases: Vec<DatabaseStatus>,
+    /// Databases that could not be opened, or reopened after a panic, until they are opened again.
+    pub failed: Vec<FailedDatabase>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedDatabase {
+    pub database: String,
+    pub error: crate::Error,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseStatus {
+    pub database: String,
+    /// Commands waiting for this database's worker.
+    pub queued: usize,
+    pub by_priority: PriorityCounts,
+    /// Callers waiting for room in the queue.
+    pub waiting: usize,
+    pub running: bool,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub struct PriorityCounts {
+    pub high: usize,
+    pub normal: usize,
+    pub low: usize,
+}
+
+/// Database files are recorded as strings, so their paths must be valid UTF-8.
+fn path_string(path: &Path) -> Result<String, crate::Error> {
+    path.to_str()
+        .map(str::to_string)
+        .ok_or_else(|| crate::Error::io(format!("Path {:?} is not valid UTF-8", path)))
+}
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct SerializedDatabase {
     pub key: String,
     pub file: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// A directory deleted when dropped.
+pub struct ScratchDir(PathBuf);
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
 }
 
 pub struct PoloDatabase {
     pub key: String,
     pub database: Database,
     pub file: String,
+    pub read_only: bool,
+    /// Backing directory of an in-memory database. Declared after `database` so it is removed only
+    /// once the database has been dropped.
+    pub scratch: Option<ScratchDir>,
 }
 
 impl PoloDatabase {
     pub fn deserialize(serialized: SerializedDatabase) -> Result<Self, crate::Error> {
-        let db = Database::open_path(Path::new(serialized.file.as_str())).or_else(|e| {
-            Err(crate::Error::Io(format!(
-                "Failed to open {:?}: {:?}",
-                serialized.file.as_str(),
-                e
-            )))
-        })?;
+        let db = Database::open_path(Path::new(serialized.file.as_str()))?;
         Ok(PoloDatabase {
             key: serialized.key,
             database: db,
             file: serialized.file,
+            read_only: serialized.read_only,
+            scratch: None,
         })
     }
 
@@ -39,6 +291,7 @@ impl PoloDatabase {
         SerializedDatabase {
             key: self.key.clone(),
             file: self.file.clone(),
+            read_only: self.read_only,
         }
     }
 
@@ -46,29 +299,448 @@ impl PoloDatabase {
         self.database.collection::<T>(name.as_ref())
     }
 
+    pub fn in_memory(&self) -> bool {
+        self.scratch.is_some()
+    }
+
     pub fn collections(&self) -> Result<Vec<String>, crate::Error> {
         self.database
             .list_collection_names()
-            .or(Err(crate::Error::DatabaseError(
-                "Failed to list collections".to_string(),
-            )))
+            .map(|names| {
+                names
+                    .into_iter()
+                    .filter(|name| name != INDEX_REGISTRY)
+                    .collect()
+            })
+            .map_err(crate::Error::from)
+    }
+}
+
+/// The databases that have been opened, persisted so they can be reopened on the next launch.
+/// Closing a database does not forget it; only [`KnownDatabases::forget`] does.
+pub struct KnownDatabases {
+    path: Option<PathBuf>,
+    entries: Vec<SerializedDatabase>,
+}
+
+impl KnownDatabases {
+    /// Reads the registry at `path`, starting empty if it is missing or unreadable. Without a path
+    /// nothing is persisted.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        KnownDatabases { path, entries }
+    }
+
+    fn save(&self) -> Result<(), crate::Error> {
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let data =
+                serde_json::to_vec_pretty(&self.entries).map_err(crate::Error::serialization)?;
+            std::fs::write(path, data)?;
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Vec<SerializedDatabase> {
+        self.entries.clone()
+    }
+
+    pub fn record(&mut self, database: SerializedDatabase) -> Result<(), crate::Error> {
+        self.entries.retain(|d| d.key != database.key);
+        self.entries.push(database);
+        self.save()
+    }
+
+    /// Removes `key` from the registry, returning whether it was known.
+    pub fn forget<K: AsRef<str>>(&mut self, key: K) -> Result<bool, crate::Error> {
+        let before = self.entries.len();
+        self.entries.retain(|d| d.key != key.as_ref());
+        if self.entries.len() == before {
+            return Ok(false);
+        }
+        self.save().and(Ok(true))
+    }
+}
+
+/// A collection handle that either autocommits or runs inside an open transaction.
+pub enum PoloCollection {
+    Direct(Collection<Document>),
+    Transactional(TransactionalCollection<Document>),
+}
+
+impl PoloCollection {
+    pub fn insert_many(&self, documents: &[Document]) -> polodb_core::Result<InsertManyResult> {
+        with_collection!(self, c => c.insert_many(documents))
+    }
+
+    pub fn find(&self, filter: Document) -> Find<'_, '_, Document> {
+        with_collection!(self, c => c.find(filter))
+    }
+
+    /// Starts a find query with `options` applied, except for the projection.
+    pub fn query(
+        &self,
+        filter: Document,
+        options: &FindOptions,
+    ) -> polodb_core::Result<ClientCursor<Document>> {
+        let mut find = self.find(filter);
+        if let Some(sort) = &options.sort {
+            find = find.sort(sort.clone());
+        }
+        if let Some(skip) = options.skip {
+            find = find.skip(skip);
+        }
+        if let Some(limit) = options.limit {
+            find = find.limit(limit);
+        }
+        find.run()
+    }
+
+    pub fn aggregate(&self, pipeline: Vec<Document>) -> Aggregate<'_, '_> {
+        with_collection!(self, c => c.aggregate(pipeline))
+    }
+
+    pub fn find_one(&self, filter: Document) -> polodb_core::Result<Option<Document>> {
+        with_collection!(self, c => c.find_one(filter))
+    }
+
+    /// Runs a find with `options` applied, projection included, returning at most one document for
+    /// [`CountSelect::One`].
+    pub fn find_documents(
+        &self,
+        query: Document,
+        count: CountSelect,
+        options: FindOptions,
+    ) -> Result<Vec<Document>, crate::Error> {
+        let projection = options.projection()?;
+        let options = match count {
+            CountSelect::Many => options,
+            CountSelect::One => FindOptions {
+                limit: Some(1),
+                ..options
+            },
+        };
+        Ok(self
+            .query(query, &options)?
+            .filter_map(Result::ok)
+            .map(|d| match &projection {
+                Some(p) => p.apply(d),
+                None => d,
+            })
+            .collect())
+    }
+
+    /// Counts the documents matching `query` without reading them out of the daemon.
+    pub fn count(&self, query: Document) -> Result<u64, crate::Error> {
+        if query.is_empty() {
+            return self.estimated_count();
+        }
+        let mut count = 0;
+        for document in self.find(query).run()? {
+            document?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// The number of documents in the collection, without filtering.
+    pub fn estimated_count(&self) -> Result<u64, crate::Error> {
+        with_collection!(self, c => c.count_documents()).map_err(crate::Error::from)
+    }
+
+    /// The distinct values of the dotted `field` across the documents matching `query`, in the order
+    /// they are first found. Array values contribute each of their elements.
+    pub fn distinct(&self, field: &str, query: Document) -> Result<Vec<Bson>, crate::Error> {
+        let path = field.split('.').collect::<Vec<&str>>();
+        let mut seen = HashSet::new();
+        let mut distinct = Vec::new();
+        for document in self.find(query).run()? {
+            let mut values = Vec::new();
+            values_at(&Bson::Document(document?), &path, &mut values);
+            for value in values {
+                if seen.insert(DistinctKey::of(&value)) {
+                    distinct.push(value);
+                }
+            }
+        }
+        Ok(distinct)
+    }
+
+    pub fn aggregate_documents(
+        &self,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, crate::Error> {
+        self.aggregate(pipeline)
+            .run()
+            .and_then(|s| s.collect::<Result<Vec<Document>, _>>())
+            .map_err(crate::Error::from)
+    }
+
+    /// Returns the ids of the documents a write with this `query` and `count` would affect.
+    pub fn matching_ids(
+        &self,
+        query: Document,
+        count: &CountSelect,
+    ) -> polodb_core::Result<Vec<Bson>> {
+        let mut ids = Vec::new();
+        let limit = match count {
+            CountSelect::One => 1,
+            CountSelect::Many => usize::MAX,
+        };
+        for document in self.find(query).run()?.take(limit) {
+            if let Some(id) = document?.remove("_id") {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Inserts the document an upsert that matched nothing creates and applies `update` to it,
+    /// returning its `_id`. Run it inside a transaction, so the document is never seen half done.
+    pub fn upsert(&self, query: &Document, update: Document) -> Result<Bson, crate::Error> {
+        let (on_insert, update) = split_set_on_insert(update)?;
+        let id = self
+            .insert_many(&[upsert_seed(query, on_insert)?])?
+            .inserted_ids
+            .remove(&0)
+            .unwrap_or(Bson::Null);
+        if !update.is_empty() {
+            self.update_one_with_options(
+                doc! {"_id": id.clone()},
+                update,
+                UpdateOptions {
+                    upsert: Some(false),
+                },
+            )?;
+        }
+        Ok(id)
+    }
+
+    pub fn find_by_ids(&self, ids: &[Bson]) -> polodb_core::Result<Vec<Document>> {
+        self.find(doc! {"_id": {"$in": ids.to_vec()}})
+            .run()?
+            .collect()
+    }
+
+    pub fn delete_many(&self, query: Document) -> polodb_core::Result<DeleteResult> {
+        with_collection!(self, c => c.delete_many(query))
+    }
+
+    pub fn delete_one(&self, query: Document) -> polodb_core::Result<DeleteResult> {
+        with_collection!(self, c => c.delete_one(query))
+    }
+
+    pub fn update_many_with_options(
+        &self,
+        query: Document,
+        update: Document,
+        options: UpdateOptions,
+    ) -> polodb_core::Result<UpdateResult> {
+        with_collection!(self, c => c.update_many_with_options(query, update, options))
+    }
+
+    pub fn update_one_with_options(
+        &self,
+        query: Document,
+        update: Document,
+        options: UpdateOptions,
+    ) -> polodb_core::Result<UpdateResult> {
+        with_collection!(self, c => c.update_one_with_options(query, update, options))
+    }
+
+    /// Swaps the document `id` for `replacement`, which keeps that `_id`, and returns the document
+    /// now stored. Run it inside a transaction, so the document is never seen missing.
+    pub fn replace(&self, id: &Bson, mut replacement: Document) -> Result<Document, crate::Error> {
+        match replacement.remove("_id") {
+            Some(other) if other != *id => {
+                return Err(crate::Error::invalid_query(
+                    "A replacement cannot change the document's _id",
+                ))
+            }
+            _ => (),
+        }
+        let mut document = doc! {"_id": id.clone()};
+        document.extend(replacement);
+        self.delete_one(doc! {"_id": id.clone()})?;
+        self.insert_many(&[document.clone()])?;
+        Ok(document)
+    }
+
+    pub fn create_index(&self, index: IndexModel) -> polodb_core::Result<()> {
+        with_collection!(self, c => c.create_index(index))
+    }
+
+    pub fn drop_index<N: AsRef<str>>(&self, name: N) -> polodb_core::Result<()> {
+        with_collection!(self, c => c.drop_index(name.as_ref()))
+    }
+
+    pub fn drop(&self) -> polodb_core::Result<()> {
+        with_collection!(self, c => c.drop())
+    }
+}
+
+/// What makes a value distinct: numbers compare by value whatever their type, as in MongoDB, and
+/// everything else by its type and content.
+#[derive(PartialEq, Eq, Hash)]
+enum DistinctKey {
+    Integer(i64),
+    Double(u64),
+    Array(Vec<DistinctKey>),
+    Document(Vec<(String, DistinctKey)>),
+    Other(String),
+}
+
+impl DistinctKey {
+    fn of(value: &Bson) -> Self {
+        match value {
+            Bson::Int32(n) => DistinctKey::Integer(*n as i64),
+            Bson::Int64(n) => DistinctKey::Integer(*n),
+            Bson::Double(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                DistinctKey::Integer(*n as i64)
+            }
+            Bson::Double(n) if n.is_nan() => DistinctKey::Double(f64::NAN.to_bits()),
+            Bson::Double(n) => DistinctKey::Double(n.to_bits()),
+            Bson::Array(values) => DistinctKey::Array(values.iter().map(DistinctKey::of).collect()),
+            Bson::Document(document) => DistinctKey::Document(
+                document
+                    .iter()
+                    .map(|(key, value)| (key.clone(), DistinctKey::of(value)))
+                    .collect(),
+            ),
+            value => DistinctKey::Other(value.clone().into_canonical_extjson().to_string()),
+        }
+    }
+}
+
+/// Splits the `$setOnInsert` fields off `update`. PoloDB does not know the operator, which only
+/// applies when an upsert inserts.
+fn split_set_on_insert(mut update: Document) -> Result<(Document, Document), crate::Error> {
+    match update.remove("$setOnInsert") {
+        Some(Bson::Document(on_insert)) => Ok((on_insert, update)),
+        Some(_) => Err(crate::Error::InvalidUpdate {
+            message: "$setOnInsert must be a document".to_string(),
+        }),
+        None => Ok((Document::new(), update)),
+    }
+}
+
+/// The document an upsert that matched nothing starts from, before its update is applied: the
+/// equality fields of `query` with the `$setOnInsert` fields `on_insert`.
+fn upsert_seed(query: &Document, on_insert: Document) -> Result<Document, crate::Error> {
+    let mut document = Document::new();
+    for (key, value) in query {
+        if key.starts_with('$') {
+            continue;
+        }
+        match value {
+            Bson::Document(condition) if condition.keys().any(|k| k.starts_with('$')) => {
+                if let Some(value) = condition.get("$eq") {
+                    set_path(&mut document, key, value.clone())?;
+                }
+            }
+            value => set_path(&mut document, key, value.clone())?,
+        }
+    }
+    for (key, value) in on_insert {
+        set_path(&mut document, &key, value)?;
+    }
+    Ok(document)
+}
+
+/// Sets the dotted `path` in `document` to `value`, creating embedded documents along the way.
+fn set_path(document: &mut Document, path: &str, value: Bson) -> Result<(), crate::Error> {
+    match path.split_once('.') {
+        None => {
+            document.insert(path, value);
+            Ok(())
+        }
+        Some((head, rest)) => {
+            let child = document
+                .entry(head.to_string())
+                .or_insert_with(|| Bson::Document(Document::new()));
+            match child {
+                Bson::Document(child) => set_path(child, rest, value),
+                _ => Err(crate::Error::InvalidUpdate {
+                    message: format!(
+                        "Cannot set {:?} inside a field that is not a document",
+                        path
+                    ),
+                }),
+            }
+        }
     }
 }
 
+/// Collects the values found at `path` under `value`, descending into arrays along the way.
+fn values_at(value: &Bson, path: &[&str], values: &mut Vec<Bson>) {
+    match (value, path.split_first()) {
+        (Bson::Array(items), _) => items.iter().for_each(|item| values_at(item, path, values)),
+        (Bson::Document(document), Some((field, rest))) => {
+            if let Some(value) = document.get(*field) {
+                values_at(value, rest, values)
+            }
+        }
+        (_, None) => values.push(value.clone()),
+        _ => {}
+    }
+}
+
+pub struct OpenTransaction {
+    pub database: String,
+    pub transaction: Transaction,
+    pub owner: Option<String>,
+    pub last_used: Instant,
+    /// Change records held back until the transaction commits.
+    pub changes: Vec<ChangeRecord>,
+}
+
+pub struct OpenCursor {
+    pub database: String,
+    pub cursor: ClientCursor<Document>,
+    pub projection: Option<Projection>,
+    pub last_used: Instant,
+}
+
 pub mod messages {
     use std::{
-        path::Path,
-        sync::{Arc, Mutex},
-        thread::{spawn, JoinHandle},
+        collections::{HashMap, VecDeque},
+        future::{poll_fn, Future},
+        panic::{catch_unwind, AssertUnwindSafe},
+        path::{Path, PathBuf},
+        pin::{pin, Pin},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Condvar, Mutex, MutexGuard, Weak,
+        },
+        task::Poll,
+        thread::{scope, sleep, spawn, JoinHandle},
+        time::{Duration, Instant},
     };
 
-    use async_channel::{unbounded, Receiver, Sender};
-    use polodb_core::{bson::Document, options::UpdateOptions, CollectionT};
+    use async_channel::{bounded, unbounded, Receiver, Sender, TrySendError};
+    use polodb_core::bson::Document;
     use serde::{de::DeserializeOwned, Deserialize, Serialize};
     use serde_json::Value;
     use uuid::Uuid;
 
-    use super::PoloDaemon;
+    use super::{
+        DaemonStatus, DatabaseStatus, FailedDatabase, FindAndModifyOptions, FindOptions,
+        IndexDefinition, KnownDatabases, PoloCollection, PoloDaemon, Priority, PriorityCounts,
+        RequestOptions, SerializedDatabase, INDEX_REGISTRY,
+    };
+    use crate::{
+        changes::{ChangeHub, ChangeRecord},
+        config::{Backpressure, DaemonOptions, IndexDeclaration},
+        error::from_response,
+        scope::Handle,
+        timer::Deadline,
+    };
 
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub enum CountSelect {
@@ -76,28 +748,57 @@ pub mod messages {
         Many,
     }
 
+    /// What a find-and-modify command does to the document it finds.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub enum Modification {
+        Update(Document),
+        Replace(Document),
+        Delete,
+    }
+
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub enum PoloCommand {
         Kill,
+        Maintain,
         OpenDatabase {
             key: String,
             path: String,
+            read_only: bool,
         },
         CloseDatabase(String),
+        OpenMemoryDatabase(String),
+        SaveAs {
+            key: String,
+            path: String,
+        },
         ListDatabases,
+        ListKnownDatabases,
+        ForgetDatabase(String),
+        RestoreDatabase(SerializedDatabase),
         ListCollections {
             database: String,
         },
+        BeginTransaction {
+            database: String,
+            owner: Option<String>,
+        },
+        CommitTransaction(Uuid),
+        AbortTransaction(Uuid),
+        ReleaseTransactions {
+            owner: String,
+        },
         Insert {
             database: String,
             collection: String,
             value: Vec<Document>,
+            transaction: Option<Uuid>,
         },
         Delete {
             database: String,
             collection: String,
             query: Document,
             count: CountSelect,
+            transaction: Option<Uuid>,
         },
         Update {
             database: String,
@@ -106,18 +807,181 @@ pub mod messages {
             update: Document,
             count: CountSelect,
             upsert: bool,
+            transaction: Option<Uuid>,
+        },
+        Replace {
+            database: String,
+            collection: String,
+            query: Document,
+            replacement: Document,
+            upsert: bool,
+            transaction: Option<Uuid>,
+        },
+        FindAndModify {
+            database: String,
+            collection: String,
+            query: Document,
+            modification: Modification,
+            options: FindAndModifyOptions,
+            transaction: Option<Uuid>,
         },
         Find {
             database: String,
             collection: String,
             query: Document,
             count: CountSelect,
-            sort: Option<Document>,
+            options: FindOptions,
+            transaction: Option<Uuid>,
+        },
+        OpenCursor {
+            database: String,
+            collection: String,
+            query: Document,
+            options: FindOptions,
+            transaction: Option<Uuid>,
+        },
+        NextBatch {
+            cursor: Uuid,
+            size: usize,
+        },
+        CloseCursor(Uuid),
+        Count {
+            database: String,
+            collection: String,
+            query: Document,
+            transaction: Option<Uuid>,
+        },
+        EstimatedCount {
+            database: String,
+            collection: String,
+            transaction: Option<Uuid>,
+        },
+        Distinct {
+            database: String,
+            collection: String,
+            field: String,
+            query: Document,
+            transaction: Option<Uuid>,
+        },
+        Aggregate {
+            database: String,
+            collection: String,
+            pipeline: Vec<Document>,
+            transaction: Option<Uuid>,
+        },
+        CreateIndex {
+            database: String,
+            collection: String,
+            index: IndexDefinition,
+            transaction: Option<Uuid>,
+        },
+        DropIndex {
+            database: String,
+            collection: String,
+            name: String,
+            transaction: Option<Uuid>,
+        },
+        ListIndexes {
+            database: String,
+            collection: String,
+        },
+        DropCollection {
+            database: String,
+            collection: String,
+            transaction: Option<Uuid>,
         },
     }
 
+    impl PoloCommand {
+        /// The database this command operates on, if it names one.
+        pub fn database(&self) -> Option<&str> {
+            match self {
+                PoloCommand::OpenDatabase { key, .. }
+                | PoloCommand::SaveAs { key, .. }
+                | PoloCommand::RestoreDatabase(SerializedDatabase { key, .. }) => Some(key),
+                PoloCommand::CloseDatabase(key) | PoloCommand::OpenMemoryDatabase(key) => Some(key),
+                PoloCommand::ListCollections { database }
+                | PoloCommand::BeginTransaction { database, .. }
+                | PoloCommand::Insert { database, .. }
+                | PoloCommand::Delete { database, .. }
+                | PoloCommand::Update { database, .. }
+                | PoloCommand::Replace { database, .. }
+                | PoloCommand::FindAndModify { database, .. }
+                | PoloCommand::Find { database, .. }
+                | PoloCommand::OpenCursor { database, .. }
+                | PoloCommand::Count { database, .. }
+                | PoloCommand::EstimatedCount { database, .. }
+                | PoloCommand::Distinct { database, .. }
+                | PoloCommand::Aggregate { database, .. }
+                | PoloCommand::CreateIndex { database, .. }
+                | PoloCommand::DropIndex { database, .. }
+                | PoloCommand::ListIndexes { database, .. }
+                | PoloCommand::DropCollection { database, .. } => Some(database),
+                _ => None,
+            }
+        }
+
+        /// The collection this command operates on, if it names one.
+        pub fn collection(&self) -> Option<&str> {
+            match self {
+                PoloCommand::Insert { collection, .. }
+                | PoloCommand::Delete { collection, .. }
+                | PoloCommand::Update { collection, .. }
+                | PoloCommand::Replace { collection, .. }
+                | PoloCommand::FindAndModify { collection, .. }
+                | PoloCommand::Find { collection, .. }
+                | PoloCommand::OpenCursor { collection, .. }
+                | PoloCommand::Count { collection, .. }
+                | PoloCommand::EstimatedCount { collection, .. }
+                | PoloCommand::Distinct { collection, .. }
+                | PoloCommand::Aggregate { collection, .. }
+                | PoloCommand::CreateIndex { collection, .. }
+                | PoloCommand::DropIndex { collection, .. }
+                | PoloCommand::ListIndexes { collection, .. }
+                | PoloCommand::DropCollection { collection, .. } => Some(collection),
+                _ => None,
+            }
+        }
+
+        /// Whether this command may run alongside others of its kind: reads outside a transaction.
+        fn concurrent(&self) -> bool {
+            matches!(
+                self,
+                PoloCommand::Find {
+                    transaction: None,
+                    ..
+                } | PoloCommand::Count {
+                    transaction: None,
+                    ..
+                } | PoloCommand::EstimatedCount {
+                    transaction: None,
+                    ..
+                } | PoloCommand::Distinct {
+                    transaction: None,
+                    ..
+                } | PoloCommand::Aggregate {
+                    transaction: None,
+                    ..
+                }
+            )
+        }
+
+        /// Whether this command opens, closes or begins something, which the default request timeout
+        /// does not apply to: giving up on it half way would leave a database or transaction behind.
+        fn lifecycle(&self) -> bool {
+            matches!(
+                self,
+                PoloCommand::OpenDatabase { .. }
+                    | PoloCommand::OpenMemoryDatabase(_)
+                    | PoloCommand::SaveAs { .. }
+                    | PoloCommand::RestoreDatabase(_)
+                    | PoloCommand::CloseDatabase(_)
+                    | PoloCommand::BeginTransaction { .. }
+            )
+        }
+    }
+
     #[derive(Clone, Debug)]
-    #[allow(dead_code)]
     pub struct PoloMessage {
         id: Uuid,
         content: PoloCommand,
@@ -126,286 +990,1283 @@ pub mod messages {
 
     impl PoloMessage {
         pub fn respond<T: Serialize>(&self, data: Result<T, crate::Error>) -> () {
-            let _ = self.return_pipe.send_blocking(data.and_then(|d| {
-                serde_json::to_value(d).or(Err(crate::Error::SerializationError(
-                    "Response serialization failure".to_string(),
-                )))
-            }));
+            self.deliver(data);
+        }
+
+        /// Sends the response, returning whether its caller was still waiting for it.
+        fn deliver<T: Serialize>(&self, data: Result<T, crate::Error>) -> bool {
+            self.return_pipe.send_blocking(data.and_then(value)).is_ok()
+        }
+
+        /// Whether nobody is waiting for this message any more, because its caller timed out,
+        /// cancelled it or went away. Broadcasts never have anyone waiting and are not abandoned.
+        fn abandoned(&self) -> bool {
+            self.return_pipe.is_closed() && !matches!(self.content, PoloCommand::Maintain)
         }
     }
 
-    #[derive(Clone, Debug)]
-    pub struct PoloManager {
-        handle: Arc<Mutex<JoinHandle<()>>>,
-        tx: Sender<PoloMessage>,
+    /// A request whose caller is still waiting for it.
+    struct Pending {
+        pipe: Sender<Result<Value, crate::Error>>,
+        handle: Handle,
     }
 
-    impl PoloManager {
-        fn daemon(rx: Receiver<PoloMessage>) -> () {
-            #[allow(unused_variables, unused_mut)]
-            let mut daemon = PoloDaemon::new();
+    impl Pending {
+        /// Fails the request with [`crate::Error::Cancelled`] and tells its worker to skip it.
+        fn cancel(self) {
+            let _ = self.pipe.try_send(Err(crate::Error::Cancelled));
+            self.pipe.close();
+        }
+    }
+
+    /// Forgets a pending request once its caller stops waiting, whether it got a response or not.
+    struct Tracked<'a> {
+        shared: &'a Shared,
+        id: Uuid,
+    }
+
+    impl Drop for Tracked<'_> {
+        fn drop(&mut self) {
+            if let Ok(mut pending) = self.shared.pending.lock() {
+                pending.remove(&self.id);
+            }
+        }
+    }
+
+    fn value<T: Serialize>(data: T) -> Result<Value, crate::Error> {
+        serde_json::to_value(data).map_err(crate::Error::serialization)
+    }
+
+    /// Most reads a worker runs side by side before going back to its queue.
+    const READ_BATCH: usize = 8;
+
+    /// How long a low priority command waits before it is picked up like a normal one, so a steady
+    /// stream of normal commands cannot starve it.
+    const LOW_PRIORITY_AGING: Duration = Duration::from_secs(1);
+
+    struct Worker {
+        queue: Arc<Queue>,
+        handle: JoinHandle<()>,
+    }
+
+    struct Queued {
+        message: PoloMessage,
+        /// Whether it holds one of the queue's slots.
+        slot: bool,
+        /// Its place in the order messages were sent in, across every priority.
+        sequence: u64,
+        since: Instant,
+    }
+
+    impl Queued {
+        fn write(&self) -> bool {
+            !self.message.content.concurrent()
+        }
+    }
+
+    #[derive(Default)]
+    struct Lanes {
+        /// Queued messages by priority.
+        messages: [VecDeque<Queued>; 3],
+        next: u64,
+        closed: bool,
+    }
+
+    impl Lanes {
+        /// The lane to take the next message from: the most urgent one whose first message does not
+        /// have to wait for an earlier write, so a read never runs before a write it was sent after.
+        /// Low priority messages that waited [`LOW_PRIORITY_AGING`] count as normal ones.
+        fn next_lane(&self, now: Instant) -> Option<usize> {
+            let mut fronts = self
+                .messages
+                .iter()
+                .enumerate()
+                .filter_map(|(lane, messages)| {
+                    let front = messages.front()?;
+                    let aged = lane == Priority::Low.lane()
+                        && now.saturating_duration_since(front.since) >= LOW_PRIORITY_AGING;
+                    let rank = if aged { Priority::Normal.lane() } else { lane };
+                    Some((rank, front.sequence, lane))
+                })
+                .collect::<Vec<(usize, u64, usize)>>();
+            fronts.sort();
+            // The oldest message always qualifies, as nothing was sent before it.
+            fronts
+                .iter()
+                .find(|(_, sequence, _)| {
+                    !self.messages.iter().any(|messages| {
+                        messages
+                            .iter()
+                            .take_while(|queued| queued.sequence < *sequence)
+                            .any(Queued::write)
+                    })
+                })
+                .map(|(_, _, lane)| *lane)
+        }
+    }
+
+    enum Reservation {
+        Reserved,
+        Full,
+        Closed,
+    }
+
+    /// A worker's command queue. Each command sent by a caller takes one of a limited number of
+    /// slots until the worker picks it up, and more urgent commands are picked up first.
+    struct Queue {
+        lanes: Mutex<Lanes>,
+        ready: Condvar,
+        /// Holds a token for every slot in use, so senders can wait for one to free up.
+        slots: (Sender<()>, Receiver<()>),
+        /// Callers waiting for a slot.
+        waiting: AtomicUsize,
+    }
+
+    impl Queue {
+        fn new(capacity: usize) -> Self {
+            Queue {
+                lanes: Mutex::new(Lanes::default()),
+                ready: Condvar::new(),
+                slots: match capacity {
+                    0 => unbounded(),
+                    capacity => bounded(capacity),
+                },
+                waiting: AtomicUsize::new(0),
+            }
+        }
+
+        fn lanes(&self) -> MutexGuard<'_, Lanes> {
+            self.lanes.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        /// Takes a slot, waiting for one to free up unless `backpressure` says to give up.
+        async fn reserve(&self, backpressure: Backpressure) -> Reservation {
+            match self.slots.0.try_send(()) {
+                Ok(()) => Reservation::Reserved,
+                Err(TrySendError::Closed(_)) => Reservation::Closed,
+                Err(TrySendError::Full(_)) if backpressure == Backpressure::Reject => {
+                    Reservation::Full
+                }
+                Err(TrySendError::Full(_)) => {
+                    self.waiting.fetch_add(1, Ordering::SeqCst);
+                    let sent = self.slots.0.send(()).await;
+                    self.waiting.fetch_sub(1, Ordering::SeqCst);
+                    match sent {
+                        Ok(()) => Reservation::Reserved,
+                        Err(_) => Reservation::Closed,
+                    }
+                }
+            }
+        }
+
+        fn release(&self) {
+            let _ = self.slots.1.try_recv();
+        }
+
+        /// Queues `message`, handing it back if the queue is closed.
+        fn push(
+            &self,
+            message: PoloMessage,
+            priority: Priority,
+            slot: bool,
+        ) -> Result<(), PoloMessage> {
+            let mut lanes = self.lanes();
+            if lanes.closed {
+                return Err(message);
+            }
+            let sequence = lanes.next;
+            lanes.next += 1;
+            lanes.messages[priority.lane()].push_back(Queued {
+                message,
+                slot,
+                sequence,
+                since: Instant::now(),
+            });
+            self.ready.notify_one();
+            Ok(())
+        }
+
+        fn take(&self, lanes: &mut Lanes) -> Option<PoloMessage> {
+            let lane = lanes.next_lane(Instant::now())?;
+            let queued = lanes.messages[lane].pop_front()?;
+            if queued.slot {
+                self.release();
+            }
+            Some(queued.message)
+        }
+
+        /// Waits for the most urgent queued message, or returns `None` once the queue is closed and
+        /// empty.
+        fn pop(&self) -> Option<PoloMessage> {
+            let mut lanes = self.lanes();
+            loop {
+                if let Some(message) = self.take(&mut lanes) {
+                    return Some(message);
+                }
+                if lanes.closed {
+                    return None;
+                }
+                lanes = self.ready.wait(lanes).unwrap_or_else(|e| e.into_inner());
+            }
+        }
+
+        fn try_pop(&self) -> Option<PoloMessage> {
+            self.take(&mut self.lanes())
+        }
+
+        /// Stops accepting messages. Those already queued can still be popped.
+        fn close(&self) {
+            self.lanes().closed = true;
+            self.slots.0.close();
+            self.ready.notify_all();
+        }
+
+        /// Closes the queue if nothing is waiting in it, returning whether it did.
+        fn close_if_empty(&self) -> bool {
+            let mut lanes = self.lanes();
+            if lanes.messages.iter().any(|lane| !lane.is_empty()) {
+                return false;
+            }
+            lanes.closed = true;
+            self.slots.0.close();
+            true
+        }
+
+        fn is_closed(&self) -> bool {
+            self.lanes().closed
+        }
+
+        fn counts(&self) -> PriorityCounts {
+            let lanes = self.lanes();
+            PriorityCounts {
+                high: lanes.messages[0].len(),
+                normal: lanes.messages[1].len(),
+                low: lanes.messages[2].len(),
+            }
+        }
+    }
+
+    /// State shared by the manager and its workers.
+    struct Shared {
+        options: DaemonOptions,
+        indexes: Vec<IndexDeclaration>,
+        known: Arc<Mutex<KnownDatabases>>,
+        changes: Sender<ChangeRecord>,
+        subscribers: ChangeHub,
+        workers: Mutex<HashMap<String, Worker>>,
+        /// Who opened each open transaction or cursor, and the database it belongs to.
+        routes: Mutex<HashMap<Uuid, Handle>>,
+        pending: Mutex<HashMap<Uuid, Pending>>,
+        /// Why each database that could not be opened or reopened failed, cleared once it opens.
+        failures: Mutex<HashMap<String, crate::Error>>,
+        /// Set once shutdown has begun, after which no command is accepted.
+        closing: AtomicBool,
+        started: Instant,
+        restarts: AtomicUsize,
+        rejected: AtomicUsize,
+    }
+
+    /// Routes commands to one worker thread per open database, so a slow query on one database
+    /// never holds up another. Each worker runs its commands by priority, and in the order they
+    /// were sent within a priority, except that consecutive reads outside a transaction run
+    /// concurrently. A command never overtakes a write sent before it, whatever their priorities,
+    /// and low priority commands that waited long enough are run like normal ones.
+    #[derive(Clone)]
+    pub struct PoloManager {
+        shared: Arc<Shared>,
+    }
+
+    impl PoloManager {
+        fn work(shared: Arc<Shared>, key: String, queue: Arc<Queue>) {
+            let mut daemon = PoloDaemon::new(
+                shared.options.clone(),
+                shared.indexes.clone(),
+                shared.known.clone(),
+                shared.changes.clone(),
+                shared.subscribers.clone(),
+            );
+            let mut pending: Option<PoloMessage> = None;
+            let mut failure: Option<crate::Error> = None;
             loop {
-                if let Ok(msg) = rx.recv_blocking() {
-                    let command = msg.clone().content;
-                    #[allow(unreachable_patterns)]
-                    match command {
-                        PoloCommand::Kill => break,
-                        PoloCommand::OpenDatabase { key, path } => {
-                            msg.respond(match daemon.open(key, Path::new(path.as_str())) {
-                                Ok(_) => Ok("Database opened.".to_string()),
-                                Err(e) => Err(e),
-                            })
+                let msg = match pending.take() {
+                    Some(msg) => msg,
+                    None => match queue.pop() {
+                        Some(msg) => msg,
+                        None => break,
+                    },
+                };
+                if let PoloCommand::Kill = msg.content {
+                    msg.respond(daemon.shutdown());
+                    break;
+                }
+                if msg.abandoned() {
+                    continue;
+                }
+
+                if msg.content.concurrent() {
+                    let mut batch = vec![msg];
+                    while batch.len() < READ_BATCH {
+                        match queue.try_pop() {
+                            Some(next) if next.abandoned() => (),
+                            Some(next) if next.content.concurrent() => batch.push(next),
+                            Some(next) => {
+                                pending = Some(next);
+                                break;
+                            }
+                            None => break,
+                        }
+                    }
+                    if PoloManager::read(&mut daemon, batch) {
+                        (daemon, failure) = PoloManager::restart(daemon, &shared);
+                    }
+                } else if let Err(panic) =
+                    catch_unwind(AssertUnwindSafe(|| PoloManager::handle(&mut daemon, &msg)))
+                {
+                    msg.respond::<()>(Err(crate::Error::panicked(panic)));
+                    (daemon, failure) = PoloManager::restart(daemon, &shared);
+                }
+
+                // A database that could not be reopened retires its worker, so the next open starts
+                // afresh, and everything waiting on it is told why.
+                if let Some(error) = &failure {
+                    if let Ok(mut failures) = shared.failures.lock() {
+                        failures.insert(key.clone(), error.clone());
+                    }
+                    if let Ok(mut workers) = shared.workers.lock() {
+                        workers.remove(&key);
+                        queue.close();
+                    }
+                    if let Ok(mut routes) = shared.routes.lock() {
+                        routes.retain(|_, h| h.database.as_ref() != Some(&key));
+                    }
+                    break;
+                }
+
+                // A worker whose database was closed (or failed to open) retires, unless more work
+                // was queued for it in the meantime.
+                if daemon.databases.is_empty() && pending.is_none() {
+                    if let Ok(mut workers) = shared.workers.lock() {
+                        if queue.close_if_empty() {
+                            workers.remove(&key);
+                            break;
                         }
-                        PoloCommand::CloseDatabase(key) => msg.respond(match daemon.close(key) {
-                            Ok(_) => Ok("Database closed.".to_string()),
-                            Err(e) => Err(e),
-                        }),
-                        PoloCommand::ListDatabases => msg.respond(Ok(daemon.list().clone())),
-                        PoloCommand::ListCollections { database } => {
-                            msg.respond(daemon.get_database_collections(database))
+                    }
+                }
+            }
+
+            // Anything queued behind a shutdown or a failed restart is turned away.
+            queue.close();
+            let error = failure.unwrap_or(crate::Error::Closed);
+            for msg in pending
+                .into_iter()
+                .chain(std::iter::from_fn(|| queue.try_pop()))
+            {
+                msg.respond::<()>(Err(error.clone()));
+            }
+        }
+
+        /// Replaces a daemon whose state may be inconsistent after a panic. Its transactions and
+        /// cursors are dropped and its databases reopened from their files. Also returns why a
+        /// database could not be reopened, in which case it is left closed.
+        fn restart(mut daemon: PoloDaemon, shared: &Shared) -> (PoloDaemon, Option<crate::Error>) {
+            shared.restarts.fetch_add(1, Ordering::SeqCst);
+            let databases = std::mem::take(&mut daemon.databases);
+            let _ = catch_unwind(AssertUnwindSafe(move || drop(daemon)));
+            let mut restarted = PoloDaemon::new(
+                shared.options.clone(),
+                shared.indexes.clone(),
+                shared.known.clone(),
+                shared.changes.clone(),
+                shared.subscribers.clone(),
+            );
+            let mut failure = None;
+            for (key, database) in databases {
+                let reopened = match Arc::try_unwrap(database) {
+                    Ok(database) => {
+                        restarted.reopen(database.into_inner().unwrap_or_else(|e| e.into_inner()))
+                    }
+                    Err(_) => Err(crate::Error::daemon("the database is still in use")),
+                };
+                if let Err(e) = reopened {
+                    failure = Some(crate::Error::daemon(format!(
+                        "failed to reopen database {:?} after a panic: {}",
+                        key, e
+                    )));
+                }
+            }
+            (restarted, failure)
+        }
+
+        /// Runs a batch of reads, side by side when there is more than one. Returns whether any of
+        /// them panicked.
+        fn read(daemon: &mut PoloDaemon, batch: Vec<PoloMessage>) -> bool {
+            let jobs = batch
+                .into_iter()
+                .map(|msg| {
+                    let collection = match &msg.content {
+                        PoloCommand::Find {
+                            database,
+                            collection,
+                            ..
                         }
-                        PoloCommand::Insert {
+                        | PoloCommand::Count {
                             database,
                             collection,
-                            value,
-                        } => {
-                            msg.respond(daemon.get_collection(database, collection).and_then(|c| {
-                                c.insert_many(value)
-                                    .and_then(|r| {
-                                        Ok(r.inserted_ids
-                                            .keys()
-                                            .map(|i| i.clone())
-                                            .collect::<Vec<usize>>())
-                                    })
-                                    .or_else(|e| Err(crate::Error::InsertError(e.to_string())))
-                            }))
+                            ..
                         }
-                        PoloCommand::Find {
+                        | PoloCommand::EstimatedCount {
                             database,
                             collection,
-                            query,
-                            count,
-                            sort,
-                        } => {
-                            msg.respond(daemon.get_collection(database, collection).and_then(|c| {
-                                match count {
-                                    CountSelect::Many => match sort {
-                                        Some(sorting) => c
-                                            .find(query)
-                                            .sort(sorting)
-                                            .run()
-                                            .and_then(|s| {
-                                                Ok(s.filter_map(|d| match d {
-                                                    Ok(v) => Some(v),
-                                                    Err(_) => None,
-                                                })
-                                                .collect())
-                                            })
-                                            .or_else(|e| {
-                                                Err(crate::Error::DatabaseError(format!(
-                                                    "Operation failed: {:?}",
-                                                    e
-                                                )))
-                                            }),
-                                        None => c
-                                            .find(query)
-                                            .run()
-                                            .and_then(|s| {
-                                                Ok(s.filter_map(|d| match d {
-                                                    Ok(v) => Some(v),
-                                                    Err(_) => None,
-                                                })
-                                                .collect())
-                                            })
-                                            .or_else(|e| {
-                                                Err(crate::Error::DatabaseError(format!(
-                                                    "Operation failed: {:?}",
-                                                    e
-                                                )))
-                                            }),
-                                    },
-                                    CountSelect::One => c
-                                        .find_one(query)
-                                        .and_then(|v| match v {
-                                            Some(d) => Ok(vec![d]),
-                                            None => Err(polodb_core::Error::UnexpectedPageType),
-                                        })
-                                        .or_else(|e| {
-                                            Err(crate::Error::DatabaseError(format!(
-                                                "Operation failed: {:?}",
-                                                e
-                                            )))
-                                        }),
-                                }
-                            }))
+                            ..
                         }
-                        PoloCommand::Delete {
+                        | PoloCommand::Distinct {
                             database,
                             collection,
-                            query,
-                            count,
-                        } => msg.respond(daemon.get_collection(database, collection).and_then(
-                            |coll| {
-                                match count {
-                                    CountSelect::Many => coll
-                                        .delete_many(query)
-                                        .or_else(|e| {
-                                            Err(crate::Error::DatabaseError(format!(
-                                                "Operation failed: {:?}",
-                                                e
-                                            )))
-                                        })
-                                        .and_then(|r| Ok(r.deleted_count)),
-                                    CountSelect::One => coll
-                                        .delete_one(query)
-                                        .or_else(|e| {
-                                            Err(crate::Error::DatabaseError(format!(
-                                                "Operation failed: {:?}",
-                                                e
-                                            )))
-                                        })
-                                        .and_then(|r| Ok(r.deleted_count)),
-                                }
-                            },
-                        )),
-                        PoloCommand::Update {
+                            ..
+                        }
+                        | PoloCommand::Aggregate {
                             database,
                             collection,
+                            ..
+                        } => daemon.get_collection(database.clone(), collection.clone(), None),
+                        _ => Err(crate::Error::daemon("Not a read")),
+                    };
+                    (collection, msg)
+                })
+                .collect::<Vec<(Result<PoloCollection, crate::Error>, PoloMessage)>>();
+
+            let run = |collection: Result<PoloCollection, crate::Error>, msg: PoloMessage| {
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    collection.and_then(|c| match msg.content.clone() {
+                        PoloCommand::Find {
                             query,
-                            update,
                             count,
-                            upsert,
-                        } => msg.respond(daemon.get_collection(database, collection).and_then(
-                            |coll| {
-                                match count {
-                                    CountSelect::Many => coll
-                                        .update_many_with_options(
-                                            query,
-                                            update,
-                                            UpdateOptions {
-                                                upsert: Some(upsert),
-                                            },
-                                        )
-                                        .or_else(|e| {
-                                            Err(crate::Error::DatabaseError(format!(
-                                                "Operation failed: {:?}",
-                                                e
-                                            )))
-                                        })
-                                        .and_then(|r| Ok(r.modified_count)),
-                                    CountSelect::One => coll
-                                        .update_one_with_options(
-                                            query,
-                                            update,
-                                            UpdateOptions {
-                                                upsert: Some(upsert),
-                                            },
-                                        )
-                                        .or_else(|e| {
-                                            Err(crate::Error::DatabaseError(format!(
-                                                "Operation failed: {:?}",
-                                                e
-                                            )))
-                                        })
-                                        .and_then(|r| Ok(r.modified_count)),
-                                }
-                            },
-                        )),
-                        _ => msg.respond::<()>(Err(crate::Error::DaemonError(
-                            "Unknown command".to_string(),
-                        ))),
-                    };
+                            options,
+                            ..
+                        } => c.find_documents(query, count, options).and_then(value),
+                        PoloCommand::Count { query, .. } => c.count(query).and_then(value),
+                        PoloCommand::EstimatedCount { .. } => c.estimated_count().and_then(value),
+                        PoloCommand::Distinct { field, query, .. } => {
+                            c.distinct(&field, query).and_then(value)
+                        }
+                        PoloCommand::Aggregate { pipeline, .. } => {
+                            c.aggregate_documents(pipeline).and_then(value)
+                        }
+                        _ => Err(crate::Error::daemon("Not a read")),
+                    })
+                }));
+                match result {
+                    Ok(result) => {
+                        msg.respond(result);
+                        false
+                    }
+                    Err(panic) => {
+                        msg.respond::<()>(Err(crate::Error::panicked(panic)));
+                        true
+                    }
                 }
+            };
+            if jobs.len() == 1 {
+                jobs.into_iter()
+                    .any(|(collection, msg)| run(collection, msg))
+            } else {
+                scope(|s| {
+                    jobs.into_iter()
+                        .map(|(collection, msg)| s.spawn(move || run(collection, msg)))
+                        .collect::<Vec<_>>()
+                        .into_iter()
+                        .fold(false, |panicked, job| {
+                            job.join().unwrap_or(true) || panicked
+                        })
+                })
             }
         }
 
-        pub fn new() -> Self {
-            let (tx, rx) = unbounded::<PoloMessage>();
-            let handle = spawn(move || PoloManager::daemon(rx));
-            PoloManager {
-                handle: Arc::new(Mutex::new(handle)),
-                tx: tx.clone(),
+        fn handle(daemon: &mut PoloDaemon, msg: &PoloMessage) {
+            let command = msg.clone().content;
+            match command {
+                PoloCommand::Maintain => {
+                    daemon.expire_transactions(daemon.options.transaction_timeout());
+                    daemon.expire_cursors(daemon.options.cursor_timeout());
+                }
+                PoloCommand::OpenDatabase {
+                    key,
+                    path,
+                    read_only,
+                } => msg.respond(
+                    match daemon.open(key, Path::new(path.as_str()), read_only) {
+                        Ok(_) => Ok("Database opened.".to_string()),
+                        Err(e) => Err(e),
+                    },
+                ),
+                PoloCommand::OpenMemoryDatabase(key) => {
+                    msg.respond(match daemon.open_memory(key) {
+                        Ok(_) => Ok("Database opened.".to_string()),
+                        Err(e) => Err(e),
+                    })
+                }
+                PoloCommand::RestoreDatabase(serialized) => msg.respond(daemon.restore(serialized)),
+                PoloCommand::SaveAs { key, path } => {
+                    msg.respond(match daemon.save_as(key, Path::new(path.as_str())) {
+                        Ok(_) => Ok("Database saved.".to_string()),
+                        Err(e) => Err(e),
+                    })
+                }
+                PoloCommand::CloseDatabase(key) => msg.respond(match daemon.close(key) {
+                    Ok(_) => Ok("Database closed.".to_string()),
+                    Err(e) => Err(e),
+                }),
+                PoloCommand::ListDatabases => msg.respond(Ok(daemon.list().clone())),
+                PoloCommand::ListCollections { database } => {
+                    msg.respond(daemon.get_database_collections(database))
+                }
+                PoloCommand::BeginTransaction { database, owner } => {
+                    match daemon.begin_transaction(database, owner) {
+                        // A caller that timed out or cancelled never learns the id, so the
+                        // transaction is rolled back rather than left open until it expires.
+                        Ok(id) => {
+                            if !msg.deliver(Ok(id)) {
+                                let _ = daemon.abort_transaction(id);
+                            }
+                        }
+                        Err(e) => msg.respond::<()>(Err(e)),
+                    }
+                }
+                PoloCommand::CommitTransaction(id) => msg.respond(daemon.commit_transaction(id)),
+                PoloCommand::AbortTransaction(id) => msg.respond(daemon.abort_transaction(id)),
+                PoloCommand::ReleaseTransactions { owner } => {
+                    msg.respond(Ok(daemon.release_transactions(owner)))
+                }
+                PoloCommand::Insert {
+                    database,
+                    collection,
+                    value,
+                    transaction,
+                } => msg.respond(daemon.insert(database, collection, value, transaction)),
+                PoloCommand::Find {
+                    database,
+                    collection,
+                    query,
+                    count,
+                    options,
+                    transaction,
+                } => msg.respond(
+                    daemon
+                        .get_collection(database, collection, transaction)
+                        .and_then(|c| c.find_documents(query, count, options)),
+                ),
+                PoloCommand::OpenCursor {
+                    database,
+                    collection,
+                    query,
+                    options,
+                    transaction,
+                } => msg.respond(daemon.open_cursor(
+                    database,
+                    collection,
+                    query,
+                    options,
+                    transaction,
+                )),
+                PoloCommand::NextBatch { cursor, size } => {
+                    msg.respond(daemon.next_batch(cursor, size))
+                }
+                PoloCommand::CloseCursor(cursor) => msg.respond(daemon.close_cursor(cursor)),
+                PoloCommand::Count {
+                    database,
+                    collection,
+                    query,
+                    transaction,
+                } => msg.respond(
+                    daemon
+                        .get_collection(database, collection, transaction)
+                        .and_then(|c| c.count(query)),
+                ),
+                PoloCommand::EstimatedCount {
+                    database,
+                    collection,
+                    transaction,
+                } => msg.respond(
+                    daemon
+                        .get_collection(database, collection, transaction)
+                        .and_then(|c| c.estimated_count()),
+                ),
+                PoloCommand::Distinct {
+                    database,
+                    collection,
+                    field,
+                    query,
+                    transaction,
+                } => msg.respond(
+                    daemon
+                        .get_collection(database, collection, transaction)
+                        .and_then(|c| c.distinct(&field, query)),
+                ),
+                PoloCommand::Aggregate {
+                    database,
+                    collection,
+                    pipeline,
+                    transaction,
+                } => msg.respond(
+                    daemon
+                        .get_collection(database, collection, transaction)
+                        .and_then(|c| c.aggregate_documents(pipeline)),
+                ),
+                PoloCommand::CreateIndex {
+                    database,
+                    collection,
+                    index,
+                    transaction,
+                } => msg.respond(daemon.create_index(database, collection, index, transaction)),
+                PoloCommand::DropIndex {
+                    database,
+                    collection,
+                    name,
+                    transaction,
+                } => msg.respond(daemon.drop_index(database, collection, name, transaction)),
+                PoloCommand::ListIndexes {
+                    database,
+                    collection,
+                } => msg.respond(daemon.list_indexes(database, collection)),
+                PoloCommand::DropCollection {
+                    database,
+                    collection,
+                    transaction,
+                } => msg.respond(daemon.drop_collection(database, collection, transaction)),
+                PoloCommand::Delete {
+                    database,
+                    collection,
+                    query,
+                    count,
+                    transaction,
+                } => msg.respond(daemon.delete(database, collection, query, count, transaction)),
+                PoloCommand::Update {
+                    database,
+                    collection,
+                    query,
+                    update,
+                    count,
+                    upsert,
+                    transaction,
+                } => msg.respond(daemon.update(
+                    database,
+                    collection,
+                    query,
+                    update,
+                    count,
+                    upsert,
+                    transaction,
+                )),
+                PoloCommand::Replace {
+                    database,
+                    collection,
+                    query,
+                    replacement,
+                    upsert,
+                    transaction,
+                } => msg.respond(daemon.replace_one(
+                    database,
+                    collection,
+                    query,
+                    replacement,
+                    upsert,
+                    transaction,
+                )),
+                PoloCommand::FindAndModify {
+                    database,
+                    collection,
+                    query,
+                    modification,
+                    options,
+                    transaction,
+                } => msg.respond(daemon.find_and_modify(
+                    database,
+                    collection,
+                    query,
+                    modification,
+                    options,
+                    transaction,
+                )),
+                _ => msg.respond::<()>(Err(crate::Error::daemon("Unknown command"))),
+            };
+        }
+
+        fn maintenance(shared: Weak<Shared>, interval: Duration) {
+            loop {
+                sleep(interval);
+                match shared.upgrade() {
+                    Some(shared) => PoloManager { shared }.broadcast(PoloCommand::Maintain),
+                    None => break,
+                }
             }
         }
 
-        pub async fn call<T: Serialize + DeserializeOwned>(
-            &self,
-            command: PoloCommand,
-        ) -> Result<T, crate::Error> {
+        /// Starts the daemon. Every committed write some subscriber of `subscribers` covers is
+        /// published as a [`ChangeRecord`] on `changes`, and opened databases are remembered in the
+        /// `known` registry file, if any.
+        pub fn new(
+            options: DaemonOptions,
+            indexes: Vec<IndexDeclaration>,
+            known: Option<PathBuf>,
+            changes: Sender<ChangeRecord>,
+            subscribers: ChangeHub,
+        ) -> Self {
+            let interval = options.maintenance_interval();
+            let shared = Arc::new(Shared {
+                options,
+                indexes,
+                known: Arc::new(Mutex::new(KnownDatabases::load(known))),
+                changes,
+                subscribers,
+                workers: Mutex::new(HashMap::new()),
+                routes: Mutex::new(HashMap::new()),
+                pending: Mutex::new(HashMap::new()),
+                failures: Mutex::new(HashMap::new()),
+                closing: AtomicBool::new(false),
+                started: Instant::now(),
+                restarts: AtomicUsize::new(0),
+                rejected: AtomicUsize::new(0),
+            });
+            let weak = Arc::downgrade(&shared);
+            spawn(move || PoloManager::maintenance(weak, interval));
+            PoloManager { shared }
+        }
+
+        fn message(command: PoloCommand) -> (PoloMessage, Receiver<Result<Value, crate::Error>>) {
             let (tx, rx) = unbounded::<Result<Value, crate::Error>>();
-            let id = Uuid::new_v4();
             let message = PoloMessage {
-                id: id.clone(),
-                content: command.clone(),
-                return_pipe: tx.clone(),
+                id: Uuid::new_v4(),
+                content: command,
+                return_pipe: tx,
             };
-            self.tx
-                .send(message.clone())
-                .await
-                .or(Err(crate::Error::DaemonError(
-                    "Channel send failure".to_string(),
-                )))?;
-            match rx.recv().await {
-                Ok(result) => match result {
-                    Ok(v) => {
-                        serde_json::from_value::<T>(v).or(Err(crate::Error::SerializationError(
-                            "Failed to deserialize reponse value".to_string(),
-                        )))
+            (message, rx)
+        }
+
+        /// Returns the queue of the worker for `key`, starting one if `start` is set.
+        fn queue(&self, key: &str, start: bool) -> Result<Arc<Queue>, crate::Error> {
+            if self.shared.closing.load(Ordering::SeqCst) {
+                return Err(crate::Error::Closed);
+            }
+            let mut workers = self
+                .shared
+                .workers
+                .lock()
+                .or(Err(crate::Error::sync("Failed to acquire worker lock")))?;
+            if let Some(worker) = workers.get(key) {
+                if !worker.queue.is_closed() {
+                    return Ok(worker.queue.clone());
+                }
+            }
+            if !start {
+                return Err(crate::Error::unknown_database(key));
+            }
+            let queue = Arc::new(Queue::new(self.shared.options.queue_capacity));
+            let shared = self.shared.clone();
+            let owned = key.to_string();
+            let worker = queue.clone();
+            let handle = spawn(move || PoloManager::work(shared, owned, worker));
+            workers.insert(
+                key.to_string(),
+                Worker {
+                    queue: queue.clone(),
+                    handle,
+                },
+            );
+            Ok(queue)
+        }
+
+        /// Queues `message` on the worker for `key`, starting one if `start` is set. When the
+        /// queue is full, waits for room or fails with [`crate::Error::Busy`] depending on the
+        /// configured backpressure.
+        async fn dispatch(
+            &self,
+            key: &str,
+            mut message: PoloMessage,
+            priority: Priority,
+            start: bool,
+        ) -> Result<(), crate::Error> {
+            loop {
+                let queue = self.queue(key, start)?;
+                match queue.reserve(self.shared.options.backpressure).await {
+                    Reservation::Reserved => (),
+                    Reservation::Full => {
+                        self.shared.rejected.fetch_add(1, Ordering::SeqCst);
+                        return Err(crate::Error::Busy {
+                            database: key.to_string(),
+                        });
                     }
-                    Err(e) => Err(e),
+                    // The worker retired or stopped in the meantime.
+                    Reservation::Closed => continue,
+                }
+                match queue.push(message, priority, true) {
+                    Ok(()) => return Ok(()),
+                    Err(returned) => {
+                        queue.release();
+                        message = returned;
+                    }
+                }
+            }
+        }
+
+        /// Queues `command` on every worker without waiting for them.
+        fn broadcast(&self, command: PoloCommand) {
+            if let Ok(workers) = self.shared.workers.lock() {
+                for worker in workers.values() {
+                    let _ = worker.queue.push(
+                        PoloManager::message(command.clone()).0,
+                        Priority::High,
+                        false,
+                    );
+                }
+            }
+        }
+
+        /// Sends `command` to every worker and collects their responses.
+        async fn gather(&self, command: PoloCommand) -> Vec<Result<Value, crate::Error>> {
+            let receivers = match self.shared.workers.lock() {
+                Ok(workers) => workers
+                    .values()
+                    .filter_map(|worker| {
+                        let (message, rx) = PoloManager::message(command.clone());
+                        worker
+                            .queue
+                            .push(message, Priority::Normal, false)
+                            .ok()
+                            .map(|_| rx)
+                    })
+                    .collect::<Vec<Receiver<Result<Value, crate::Error>>>>(),
+                Err(_) => Vec::new(),
+            };
+            let mut results = Vec::new();
+            for rx in receivers {
+                results.push(
+                    rx.recv()
+                        .await
+                        .unwrap_or(Err(crate::Error::daemon("Failed to recv daemon response"))),
+                );
+            }
+            results
+        }
+
+        fn route(&self, id: &Uuid) -> Option<String> {
+            self.shared.routes.lock().ok()?.get(id)?.database.clone()
+        }
+
+        fn set_route(&self, id: Uuid, handle: Option<Handle>) {
+            if let Ok(mut routes) = self.shared.routes.lock() {
+                match handle {
+                    Some(handle) => routes.insert(id, handle),
+                    None => routes.remove(&id),
+                };
+            }
+        }
+
+        /// Who opened the transaction, cursor or pending request `id`, and what it reaches.
+        pub fn handle(&self, id: &Uuid) -> Option<Handle> {
+            if let Some(handle) = self.shared.routes.lock().ok()?.get(id) {
+                return Some(handle.clone());
+            }
+            Some(self.shared.pending.lock().ok()?.get(id)?.handle.clone())
+        }
+
+        /// How long a request may take, from `options` or the daemon's `requestTimeout`, which
+        /// lifecycle commands are exempt from.
+        fn timeout(&self, command: &PoloCommand, options: &RequestOptions) -> Option<Duration> {
+            match options.timeout {
+                Some(0) => None,
+                Some(timeout) => Some(Duration::from_millis(timeout)),
+                None if command.lifecycle() => None,
+                None => self.shared.options.request_timeout(),
+            }
+        }
+
+        /// Runs `future`, failing with [`crate::Error::Timeout`] once `timeout` has passed since
+        /// `started`.
+        async fn within<T, F: Future<Output = Result<T, crate::Error>>>(
+            timeout: Option<Duration>,
+            started: Instant,
+            future: F,
+        ) -> Result<T, crate::Error> {
+            let timeout = match timeout {
+                Some(timeout) => timeout,
+                None => return future.await,
+            };
+            let mut future = pin!(future);
+            let mut deadline = Deadline::at(started + timeout);
+            poll_fn(|cx| match future.as_mut().poll(cx) {
+                Poll::Ready(result) => Poll::Ready(result),
+                Poll::Pending => Pin::new(&mut deadline).poll(cx).map(|result| {
+                    result.and(Err(crate::Error::Timeout {
+                        milliseconds: timeout.as_millis() as u64,
+                    }))
+                }),
+            })
+            .await
+        }
+
+        /// Records `message` as pending until the returned guard is dropped, so it can be cancelled.
+        fn track(&self, message: &PoloMessage, handle: Handle) -> Tracked<'_> {
+            if let Ok(mut pending) = self.shared.pending.lock() {
+                pending.insert(
+                    message.id,
+                    Pending {
+                        pipe: message.return_pipe.clone(),
+                        handle,
+                    },
+                );
+            }
+            Tracked {
+                shared: &self.shared,
+                id: message.id,
+            }
+        }
+
+        /// Cancels the pending request with the id `id`, returning whether there was one.
+        pub fn cancel(&self, id: Uuid) -> bool {
+            let pending = match self.shared.pending.lock() {
+                Ok(mut pending) => pending.remove(&id),
+                Err(_) => None,
+            };
+            match pending {
+                Some(pending) => {
+                    pending.cancel();
+                    true
+                }
+                None => false,
+            }
+        }
+
+        /// Cancels every pending request sent by `owner`, returning how many there were.
+        pub fn cancel_owned(&self, owner: &str) -> usize {
+            let cancelled = match self.shared.pending.lock() {
+                Ok(mut pending) => {
+                    let ids = pending
+                        .iter()
+                        .filter(|(_, p)| p.handle.owner.as_deref() == Some(owner))
+                        .map(|(id, _)| *id)
+                        .collect::<Vec<Uuid>>();
+                    ids.into_iter()
+                        .filter_map(|id| pending.remove(&id))
+                        .collect::<Vec<Pending>>()
+                }
+                Err(_) => Vec::new(),
+            };
+            let count = cancelled.len();
+            cancelled.into_iter().for_each(Pending::cancel);
+            count
+        }
+
+        async fn request(
+            &self,
+            command: PoloCommand,
+            options: &RequestOptions,
+        ) -> Result<Value, crate::Error> {
+            let routed = match &command {
+                PoloCommand::Kill | PoloCommand::Maintain => {
+                    self.broadcast(command);
+                    return Ok(Value::Null);
+                }
+                PoloCommand::ListDatabases => {
+                    let mut databases = Vec::new();
+                    for result in self.gather(command).await.into_iter().flatten() {
+                        databases.extend(
+                            serde_json::from_value::<Vec<String>>(result).unwrap_or_default(),
+                        );
+                    }
+                    return value(databases);
+                }
+                PoloCommand::ReleaseTransactions { .. } => {
+                    let mut released = 0;
+                    for result in self.gather(command).await.into_iter().flatten() {
+                        released += serde_json::from_value::<usize>(result).unwrap_or(0);
+                    }
+                    return value(released);
+                }
+                PoloCommand::ListKnownDatabases => return value(self.known()?.entries()),
+                PoloCommand::ForgetDatabase(key) => return value(self.known()?.forget(key)?),
+                PoloCommand::CommitTransaction(id) | PoloCommand::AbortTransaction(id) => {
+                    Some(self.route(id).ok_or(crate::Error::UnknownTransaction {
+                        transaction: id.to_string(),
+                    })?)
+                }
+                PoloCommand::NextBatch { cursor: id, .. } | PoloCommand::CloseCursor(id) => {
+                    Some(self.route(id).ok_or(crate::Error::UnknownCursor {
+                        cursor: id.to_string(),
+                    })?)
+                }
+                _ => None,
+            };
+
+            let database = match routed.as_deref().or(command.database()) {
+                Some(database) => database.to_string(),
+                None => return Err(crate::Error::daemon("Unknown command")),
+            };
+            // The index registry is maintained by the daemon and is never exposed to callers.
+            if command.collection() == Some(INDEX_REGISTRY) {
+                return Err(crate::Error::PermissionDenied {
+                    database,
+                    collection: INDEX_REGISTRY.to_string(),
+                    access: "all".to_string(),
+                });
+            }
+            let start = matches!(
+                command,
+                PoloCommand::OpenDatabase { .. }
+                    | PoloCommand::OpenMemoryDatabase(_)
+                    | PoloCommand::RestoreDatabase(_)
+            );
+            let (mut message, rx) = PoloManager::message(command.clone());
+            if let Some(id) = options.id {
+                message.id = id;
+            }
+            let priority = options
+                .priority
+                .unwrap_or(if options.focused && command.concurrent() {
+                    Priority::High
+                } else {
+                    Priority::Normal
+                });
+            let handle = Handle {
+                owner: match &command {
+                    PoloCommand::BeginTransaction { owner, .. } => owner.clone(),
+                    _ => options.owner.clone(),
                 },
-                Err(_) => Err(crate::Error::DaemonError(
-                    "Failed to recv daemon response".to_string(),
-                )),
+                database: Some(database.clone()),
+                collection: command.collection().map(str::to_string),
+            };
+            let _tracked = self.track(&message, handle.clone());
+            let (timeout, started) = (self.timeout(&command, options), Instant::now());
+            PoloManager::within(
+                timeout,
+                started,
+                self.dispatch(&database, message, priority, start),
+            )
+            .await?;
+            let result = PoloManager::within(timeout, started, async {
+                rx.recv()
+                    .await
+                    .unwrap_or(Err(crate::Error::daemon("Failed to recv daemon response")))
+            })
+            .await;
+
+            // A transaction begun just as its caller gave up would otherwise stay open until it
+            // expires.
+            if let (PoloCommand::BeginTransaction { .. }, Err(crate::Error::Timeout { .. })) =
+                (&command, &result)
+            {
+                rx.close();
+                if let Ok(Ok(Ok(id))) = rx.try_recv().map(|r| r.map(serde_json::from_value::<Uuid>))
+                {
+                    self.set_route(id, Some(handle.clone()));
+                    let _ = self.call_nowait(PoloCommand::AbortTransaction(id));
+                }
+            }
+
+            if start && result.is_ok() {
+                if let Ok(mut failures) = self.shared.failures.lock() {
+                    failures.remove(&database);
+                }
+            }
+            match &command {
+                PoloCommand::BeginTransaction { .. } | PoloCommand::OpenCursor { .. } => {
+                    if let Ok(Ok(id)) = result.clone().map(serde_json::from_value::<Uuid>) {
+                        self.set_route(id, Some(handle));
+                    }
+                }
+                PoloCommand::NextBatch { cursor, .. } => {
+                    let exhausted = result.as_ref().map_or(true, |batch| {
+                        batch.get("exhausted") == Some(&Value::Bool(true))
+                    });
+                    if exhausted {
+                        self.set_route(*cursor, None);
+                    }
+                }
+                PoloCommand::CommitTransaction(id)
+                | PoloCommand::AbortTransaction(id)
+                | PoloCommand::CloseCursor(id) => self.set_route(*id, None),
+                PoloCommand::CloseDatabase(key) if result.is_ok() => {
+                    if let Ok(mut routes) = self.shared.routes.lock() {
+                        routes.retain(|_, h| h.database.as_ref() != Some(key));
+                    }
+                    // Only an explicit close forgets the database; shutting down keeps it known.
+                    if let Ok(mut known) = self.known() {
+                        let _ = known.forget(key);
+                    }
+                }
+                _ => (),
             }
+            result
+        }
+
+        fn known(&self) -> Result<MutexGuard<'_, KnownDatabases>, crate::Error> {
+            self.shared
+                .known
+                .lock()
+                .or(Err(crate::Error::sync("Failed to acquire registry lock")))
+        }
+
+        pub async fn call<T: Serialize + DeserializeOwned>(
+            &self,
+            command: PoloCommand,
+        ) -> Result<T, crate::Error> {
+            self.call_with(command, &RequestOptions::default()).await
+        }
+
+        pub async fn call_with<T: Serialize + DeserializeOwned>(
+            &self,
+            command: PoloCommand,
+            options: &RequestOptions,
+        ) -> Result<T, crate::Error> {
+            from_response(self.request(command, options).await?)
         }
 
         pub async fn call_nowait(&self, command: PoloCommand) -> Result<(), crate::Error> {
-            let (tx, _) = unbounded::<Result<Value, crate::Error>>();
-            let id = Uuid::new_v4();
-            let message = PoloMessage {
-                id: id.clone(),
-                content: command.clone(),
-                return_pipe: tx.clone(),
-            };
-            self.tx
-                .send(message.clone())
-                .await
-                .or(Err(crate::Error::DaemonError(
-                    "Channel send failure".to_string(),
-                )))?;
+            let manager = self.clone();
+            tauri::async_runtime::spawn(async move {
+                let _ = manager.request(command, &RequestOptions::default()).await;
+            });
             Ok(())
         }
 
-        pub async fn kill(&self) -> Result<(), crate::Error> {
-            self.call_nowait(PoloCommand::Kill).await?;
-            self.handle
+        /// Reports how long the daemon has been up, how often it recovered from a panic and how
+        /// much work is queued for each open database.
+        pub fn status(&self) -> DaemonStatus {
+            let databases = match self.shared.workers.lock() {
+                Ok(workers) => workers
+                    .iter()
+                    .map(|(key, worker)| {
+                        let counts = worker.queue.counts();
+                        DatabaseStatus {
+                            database: key.clone(),
+                            queued: counts.high + counts.normal + counts.low,
+                            by_priority: counts,
+                            waiting: worker.queue.waiting.load(Ordering::SeqCst),
+                            running: !worker.handle.is_finished(),
+                        }
+                    })
+                    .collect::<Vec<DatabaseStatus>>(),
+                Err(_) => Vec::new(),
+            };
+            let failed = match self.shared.failures.lock() {
+                Ok(failures) => failures
+                    .iter()
+                    .map(|(database, error)| FailedDatabase {
+                        database: database.clone(),
+                        error: error.clone(),
+                    })
+                    .collect::<Vec<FailedDatabase>>(),
+                Err(_) => Vec::new(),
+            };
+            DaemonStatus {
+                uptime: self.shared.started.elapsed().as_secs(),
+                restarts: self.shared.restarts.load(Ordering::SeqCst),
+                queued: databases.iter().map(|d| d.queued).sum(),
+                capacity: self.shared.options.queue_capacity,
+                rejected: self.shared.rejected.load(Ordering::SeqCst),
+                closing: self.shared.closing.load(Ordering::SeqCst),
+                databases,
+                failed,
+            }
+        }
+
+        /// Reports in [`PoloManager::status`] that `database` could not be opened, until it is.
+        pub fn record_failure(&self, database: &str, error: crate::Error) {
+            if let Ok(mut failures) = self.shared.failures.lock() {
+                failures.insert(database.to_string(), error);
+            }
+        }
+
+        /// Stops every worker once it has run the commands already queued for it: open transactions
+        /// are rolled back and databases closed, flushing them to disk. Waits up to the configured
+        /// shutdown timeout, then reports every database that failed to close or did not stop in time.
+        /// Commands sent afterwards fail with [`crate::Error::Closed`].
+        pub fn shutdown(&self) -> Result<(), crate::Error> {
+            self.shared.closing.store(true, Ordering::SeqCst);
+            let workers = self
+                .shared
+                .workers
                 .lock()
-                .or(Err(crate::Error::DaemonError(
-                    "Handle lock failed".to_string(),
-                )))
-                .and(Ok(()))
+                .or(Err(crate::Error::sync("Failed to acquire worker lock")))?
+                .drain()
+                .collect::<Vec<(String, Worker)>>();
+            let stopping = workers
+                .into_iter()
+                .map(|(key, worker)| {
+                    // Queued last, so everything already waiting runs first.
+                    let (message, rx) = PoloManager::message(PoloCommand::Kill);
+                    let _ = worker.queue.push(message, Priority::Low, false);
+                    (key, worker, rx)
+                })
+                .collect::<Vec<(String, Worker, Receiver<Result<Value, crate::Error>>)>>();
+
+            let deadline = Instant::now() + self.shared.options.shutdown_timeout();
+            let mut failures = Vec::new();
+            for (key, worker, rx) in stopping {
+                while !worker.handle.is_finished() && Instant::now() < deadline {
+                    sleep(Duration::from_millis(10));
+                }
+                if !worker.handle.is_finished() {
+                    failures.push(format!("{:?} did not stop in time", key));
+                    continue;
+                }
+                if worker.handle.join().is_err() {
+                    failures.push(format!("{:?} stopped unexpectedly", key));
+                } else if let Ok(Err(e)) = rx.try_recv() {
+                    failures.push(format!("{:?} failed to close: {}", key, e));
+                }
+            }
+
+            if failures.is_empty() {
+                Ok(())
+            } else {
+                Err(crate::Error::daemon(failures.join("; ")))
+            }
+        }
+
+        pub async fn kill(&self) -> Result<(), crate::Error> {
+            let manager = self.clone();
+            tauri::async_runtime::spawn_blocking(move || manager.shutdown())
+                .await
+                .map_err(|_| crate::Error::daemon("Shutdown task failed"))?
         }
     }
 }
 
 pub struct PoloDaemon {
     pub databases: HashMap<String, Arc<Mutex<PoloDatabase>>>,
+    pub transactions: HashMap<Uuid, OpenTransaction>,
+    pub cursors: HashMap<Uuid, OpenCursor>,
+    pub indexes: Vec<IndexDeclaration>,
+    pub changes: Sender<ChangeRecord>,
+    pub subscribers: ChangeHub,
+    pub options: DaemonOptions,
+    pub known: Arc<Mutex<KnownDatabases>>,
 }
 
 impl PoloDaemon {
-    pub fn new() -> Self {
+    pub fn new(
+        options: DaemonOptions,
+        indexes: Vec<IndexDeclaration>,
+        known: Arc<Mutex<KnownDatabases>>,
+        changes: Sender<ChangeRecord>,
+        subscribers: ChangeHub,
+    ) -> Self {
         PoloDaemon {
             databases: HashMap::new(),
+            transactions: HashMap::new(),
+            cursors: HashMap::new(),
+            indexes,
+            changes,
+            subscribers,
+            options,
+            known,
         }
     }
 
     pub fn get<K: AsRef<str>>(&self, key: K) -> Result<MutexGuard<'_, PoloDatabase>, crate::Error> {
         match self.databases.get(key.as_ref()) {
-            Some(arc) => arc
-                .lock()
-                .or(Err(crate::Error::Sync("daemon.get".to_string()))),
-            None => Err(crate::Error::UnknownDatabase(key.as_ref().to_string())),
+            Some(arc) => arc.lock().or(Err(crate::Error::sync("daemon.get"))),
+            None => Err(crate::Error::unknown_database(key)),
         }
     }
 
@@ -413,33 +2274,218 @@ impl PoloDaemon {
         &mut self,
         key: K,
         path: F,
+        read_only: bool,
     ) -> Result<(), crate::Error> {
         if self.databases.contains_key(key.as_ref()) {
             return Ok(());
         }
-        let path_string = path.as_ref().to_str().unwrap().to_string();
-        let db = Database::open_path(path.as_ref()).or_else(|e| {
-            Err(crate::Error::Io(format!(
-                "Failed to open {:?}: {:?}",
-                path_string.clone(),
-                e
-            )))
+        let serialized = SerializedDatabase {
+            key: key.as_ref().to_string(),
+            file: path_string(path.as_ref())?,
+            read_only,
+        };
+        self.restore(serialized.clone())?;
+        self.remember(serialized);
+        Ok(())
+    }
+
+    fn remember(&self, serialized: SerializedDatabase) {
+        // The database is open either way; failing to remember it only affects the next launch.
+        if let Ok(mut known) = self.known.lock() {
+            let _ = known.record(serialized);
+        }
+    }
+
+    pub fn restore(&mut self, serialized: SerializedDatabase) -> Result<(), crate::Error> {
+        let database = PoloDatabase::deserialize(serialized)?;
+        self.attach(database)
+    }
+
+    /// Closes `database` and opens it again from its file, keeping an in-memory database's scratch
+    /// directory.
+    pub fn reopen(&mut self, database: PoloDatabase) -> Result<(), crate::Error> {
+        let PoloDatabase {
+            key,
+            database,
+            file,
+            read_only,
+            scratch,
+        } = database;
+        drop(database);
+        let mut reopened = PoloDatabase::deserialize(SerializedDatabase {
+            key,
+            file,
+            read_only,
         })?;
-        self.databases.insert(
-            key.as_ref().to_string(),
-            Arc::new(Mutex::new(PoloDatabase {
-                key: key.as_ref().to_string(),
-                database: db,
-                file: path_string.clone(),
-            })),
-        );
+        reopened.scratch = scratch;
+        self.attach(reopened)
+    }
+
+    fn attach(&mut self, database: PoloDatabase) -> Result<(), crate::Error> {
+        let key = database.key.clone();
+        let read_only = database.read_only;
+        self.databases
+            .insert(key.clone(), Arc::new(Mutex::new(database)));
+        if read_only {
+            return Ok(());
+        }
+        if let Err(e) = self.ensure_indexes(&key) {
+            self.databases.remove(&key);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Opens a database that only lives until it is closed. PoloDB 5 has no in-memory storage, so it is
+    /// backed by a scratch directory in the system temp directory, which is deleted on close.
+    pub fn open_memory<K: AsRef<str>>(&mut self, key: K) -> Result<(), crate::Error> {
+        if self.databases.contains_key(key.as_ref()) {
+            return Ok(());
+        }
+        let path = std::env::temp_dir().join(format!("polodb-memory-{}", Uuid::new_v4()));
+        let file = path_string(&path)?;
+        let scratch = ScratchDir(path.clone());
+        let database = Database::open_path(&path)?;
+        self.attach(PoloDatabase {
+            key: key.as_ref().to_string(),
+            database,
+            file,
+            read_only: false,
+            scratch: Some(scratch),
+        })
+    }
+
+    /// Copies the in-memory database `key` to a new database at `path`, which then replaces it under
+    /// the same key and is remembered like any other opened database.
+    pub fn save_as<K: AsRef<str>, F: AsRef<Path>>(
+        &mut self,
+        key: K,
+        path: F,
+    ) -> Result<(), crate::Error> {
+        let key = key.as_ref().to_string();
+        let file = path_string(path.as_ref())?;
+        if !self.get(&key)?.in_memory() {
+            return Err(crate::Error::Database {
+                message: format!("Database {:?} is not an in-memory database", key),
+            });
+        }
+        if self.transactions.values().any(|t| t.database == key) {
+            return Err(crate::Error::Transaction {
+                message: format!("Database {:?} has open transactions", key),
+            });
+        }
+        if path.as_ref().exists() {
+            return Err(crate::Error::io(format!(
+                "{:?} already exists",
+                path.as_ref()
+            )));
+        }
+
+        let target = Database::open_path(path.as_ref())?;
+        {
+            let source = self.get(&key)?;
+            for name in source.database.list_collection_names()? {
+                let documents = source
+                    .database
+                    .collection::<Document>(name.as_str())
+                    .find(doc! {})
+                    .run()?
+                    .collect::<polodb_core::Result<Vec<Document>>>()?;
+                if !documents.is_empty() {
+                    target
+                        .collection::<Document>(name.as_str())
+                        .insert_many(&documents)?;
+                }
+                if name == INDEX_REGISTRY {
+                    for record in documents {
+                        if let Ok(collection) = record.get_str("collection").map(str::to_string) {
+                            let index = from_document::<IndexDefinition>(record)?;
+                            target
+                                .collection::<Document>(collection.as_str())
+                                .create_index(index.model())?;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.cursors.retain(|_, c| c.database != key);
+        let database = PoloDatabase {
+            key: key.clone(),
+            database: target,
+            file,
+            read_only: false,
+            scratch: None,
+        };
+        let serialized = database.serialize();
+        // Replacing the in-memory database drops it, deleting its scratch directory.
+        self.databases.insert(key, Arc::new(Mutex::new(database)));
+        self.remember(serialized);
+        Ok(())
+    }
+
+    /// Creates every configured index that applies to `database`.
+    pub fn ensure_indexes<K: AsRef<str>>(&mut self, database: K) -> Result<(), crate::Error> {
+        let declarations = self
+            .indexes
+            .iter()
+            .filter(|d| d.applies_to(database.as_ref()))
+            .cloned()
+            .collect::<Vec<IndexDeclaration>>();
+        for declaration in declarations {
+            self.create_index(
+                database.as_ref().to_string(),
+                declaration.collection,
+                declaration.index,
+                None,
+            )?;
+        }
         Ok(())
     }
 
     pub fn close<K: AsRef<str>>(&mut self, key: K) -> Result<(), crate::Error> {
+        let owned = self
+            .transactions
+            .iter()
+            .filter(|(_, t)| t.database == key.as_ref())
+            .map(|(id, _)| *id)
+            .collect::<Vec<Uuid>>();
+        for id in owned {
+            let _ = self.abort_transaction(id);
+        }
+        self.cursors.retain(|_, c| c.database != key.as_ref());
         match self.databases.remove(key.as_ref()) {
             Some(_) => Ok(()),
-            None => Err(crate::Error::UnknownDatabase(key.as_ref().to_string())),
+            None => Err(crate::Error::unknown_database(key)),
+        }
+    }
+
+    /// Rolls back every open transaction and closes every database, returning the first failure.
+    pub fn shutdown(&mut self) -> Result<(), crate::Error> {
+        let mut result = Ok(());
+        let transactions = self.transactions.keys().copied().collect::<Vec<Uuid>>();
+        for id in transactions {
+            if let Err(e) = self.abort_transaction(id) {
+                result = result.and(Err(e));
+            }
+        }
+        self.cursors.clear();
+        for key in self.list() {
+            if let Err(e) = self.close(&key) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    /// Fails with [`crate::Error::ReadOnly`] if `database` was opened read-only.
+    pub fn writable<K: AsRef<str>>(&self, database: K) -> Result<(), crate::Error> {
+        if self.get(&database)?.read_only {
+            Err(crate::Error::ReadOnly {
+                database: database.as_ref().to_string(),
+            })
+        } else {
+            Ok(())
         }
     }
 
@@ -448,28 +2494,687 @@ impl PoloDaemon {
     }
 
     pub fn get_collection(
-        &self,
+        &mut self,
         database: String,
         collection: String,
-    ) -> Result<Collection<Document>, crate::Error> {
+        transaction: Option<Uuid>,
+    ) -> Result<PoloCollection, crate::Error> {
+        if let Some(id) = transaction {
+            let open = self
+                .transactions
+                .get_mut(&id)
+                .ok_or(crate::Error::UnknownTransaction {
+                    transaction: id.to_string(),
+                })?;
+            if open.database != database {
+                return Err(crate::Error::Transaction {
+                    message: format!("Transaction {} belongs to database {:?}", id, open.database),
+                });
+            }
+            open.last_used = Instant::now();
+            return Ok(PoloCollection::Transactional(
+                open.transaction.collection::<Document>(collection.as_str()),
+            ));
+        }
         let db = match self.databases.get(&database) {
-            Some(locked) => locked.lock().or(Err(crate::Error::Sync(
-                "Failed to acquire DB lock".to_string(),
-            ))),
-            None => Err(crate::Error::UnknownDatabase("Invalid DB key".to_string())),
+            Some(locked) => locked
+                .lock()
+                .or(Err(crate::Error::sync("Failed to acquire DB lock"))),
+            None => Err(crate::Error::unknown_database(&database)),
         }?;
-        Ok(db.collection::<Document, String>(collection))
+        Ok(PoloCollection::Direct(
+            db.collection::<Document, String>(collection),
+        ))
+    }
+
+    pub fn begin_transaction(
+        &mut self,
+        database: String,
+        owner: Option<String>,
+    ) -> Result<Uuid, crate::Error> {
+        let transaction = self
+            .get(&database)?
+            .database
+            .start_transaction()
+            .map_err(crate::Error::from)?;
+        let id = Uuid::new_v4();
+        self.transactions.insert(
+            id,
+            OpenTransaction {
+                database,
+                transaction,
+                owner,
+                last_used: Instant::now(),
+                changes: Vec::new(),
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn commit_transaction(&mut self, id: Uuid) -> Result<(), crate::Error> {
+        let open = self
+            .transactions
+            .remove(&id)
+            .ok_or(crate::Error::UnknownTransaction {
+                transaction: id.to_string(),
+            })?;
+        open.transaction.commit().map_err(crate::Error::from)?;
+        for record in open.changes {
+            let _ = self.changes.try_send(record);
+        }
+        Ok(())
+    }
+
+    /// Runs `run` inside a transaction of its own on `database`, committing it if `run` succeeds and
+    /// rolling it back otherwise.
+    fn within_transaction<T>(
+        &mut self,
+        database: &str,
+        run: impl FnOnce(&mut Self, Uuid) -> Result<T, crate::Error>,
+    ) -> Result<T, crate::Error> {
+        let id = self.begin_transaction(database.to_string(), None)?;
+        match run(self, id) {
+            Ok(value) => self.commit_transaction(id).map(|()| value),
+            Err(e) => {
+                let _ = self.abort_transaction(id);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn abort_transaction(&mut self, id: Uuid) -> Result<(), crate::Error> {
+        match self.transactions.remove(&id) {
+            Some(open) => open.transaction.rollback().map_err(crate::Error::from),
+            None => Err(crate::Error::UnknownTransaction {
+                transaction: id.to_string(),
+            }),
+        }
+    }
+
+    /// Rolls back every transaction that was begun by `owner`, returning how many were aborted.
+    pub fn release_transactions<O: AsRef<str>>(&mut self, owner: O) -> usize {
+        let owned = self
+            .transactions
+            .iter()
+            .filter(|(_, t)| t.owner.as_deref() == Some(owner.as_ref()))
+            .map(|(id, _)| *id)
+            .collect::<Vec<Uuid>>();
+        owned
+            .into_iter()
+            .filter(|id| self.abort_transaction(*id).is_ok())
+            .count()
+    }
+
+    /// Rolls back every transaction that has been idle for longer than `timeout`.
+    pub fn expire_transactions(&mut self, timeout: Duration) {
+        let expired = self
+            .transactions
+            .iter()
+            .filter(|(_, t)| t.last_used.elapsed() > timeout)
+            .map(|(id, _)| *id)
+            .collect::<Vec<Uuid>>();
+        for id in expired {
+            let _ = self.abort_transaction(id);
+        }
+    }
+
+    /// Publishes a change record, or holds it back until `transaction` commits.
+    fn publish(&mut self, transaction: Option<Uuid>, record: ChangeRecord) {
+        if record.ids.is_empty() && record.operation != ChangeOperation::Drop {
+            return;
+        }
+        match transaction.and_then(|id| self.transactions.get_mut(&id)) {
+            Some(open) => open.changes.push(record),
+            None => {
+                let _ = self.changes.try_send(record);
+            }
+        }
+    }
+
+    pub fn insert(
+        &mut self,
+        database: String,
+        collection: String,
+        mut documents: Vec<Document>,
+        transaction: Option<Uuid>,
+    ) -> Result<Vec<Bson>, crate::Error> {
+        self.writable(&database)?;
+        let result = self
+            .get_collection(database.clone(), collection.clone(), transaction)?
+            .insert_many(&documents)?;
+        let mut ids = result
+            .inserted_ids
+            .into_iter()
+            .collect::<Vec<(usize, Bson)>>();
+        ids.sort_by_key(|(i, _)| *i);
+        let ids = ids
+            .into_iter()
+            .map(|(i, id)| {
+                if let Some(document) = documents.get_mut(i) {
+                    document.insert("_id", id.clone());
+                }
+                id
+            })
+            .collect::<Vec<Bson>>();
+        self.publish(
+            transaction,
+            ChangeRecord::new(
+                &database,
+                &collection,
+                ChangeOperation::Insert,
+                ids.clone(),
+                Some(documents),
+            ),
+        );
+        Ok(ids)
+    }
+
+    pub fn delete(
+        &mut self,
+        database: String,
+        collection: String,
+        query: Document,
+        count: CountSelect,
+        transaction: Option<Uuid>,
+    ) -> Result<u64, crate::Error> {
+        self.writable(&database)?;
+        let coll = self.get_collection(database.clone(), collection.clone(), transaction)?;
+        let interest = self.subscribers.interest(&database, &collection);
+        let ids = match interest {
+            Interest::None => Vec::new(),
+            _ => coll.matching_ids(query.clone(), &count)?,
+        };
+        let result = match count {
+            CountSelect::Many => coll.delete_many(query),
+            CountSelect::One => coll.delete_one(query),
+        }?;
+        self.publish(
+            transaction,
+            ChangeRecord::new(&database, &collection, ChangeOperation::Delete, ids, None),
+        );
+        Ok(result.deleted_count)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        database: String,
+        collection: String,
+        query: Document,
+        update: Document,
+        count: CountSelect,
+        upsert: bool,
+        transaction: Option<Uuid>,
+    ) -> Result<u64, crate::Error> {
+        self.writable(&database)?;
+        // An upsert may update nothing and then insert, so outside a transaction it gets its own.
+        if upsert && transaction.is_none() {
+            return self.within_transaction(&database.clone(), |daemon, id| {
+                daemon.update(database, collection, query, update, count, upsert, Some(id))
+            });
+        }
+        let coll = self.get_collection(database.clone(), collection.clone(), transaction)?;
+        let interest = self.subscribers.interest(&database, &collection);
+        let ids = match interest {
+            Interest::None => Vec::new(),
+            _ => coll.matching_ids(query.clone(), &count)?,
+        };
+        // Upserts are inserted here rather than by PoloDB, which does not report the new `_id`.
+        let options = UpdateOptions {
+            upsert: Some(false),
+        };
+        let (_, matched) = split_set_on_insert(update.clone())?;
+        let result = match count {
+            CountSelect::Many => coll.update_many_with_options(query.clone(), matched, options),
+            CountSelect::One => coll.update_one_with_options(query.clone(), matched, options),
+        }?;
+        let (operation, ids) = if upsert && result.matched_count == 0 {
+            (ChangeOperation::Insert, vec![coll.upsert(&query, update)?])
+        } else {
+            (ChangeOperation::Update, ids)
+        };
+        let documents = match interest {
+            Interest::Documents => Some(coll.find_by_ids(&ids)?),
+            _ => None,
+        };
+        self.publish(
+            transaction,
+            ChangeRecord::new(&database, &collection, operation, ids, documents),
+        );
+        Ok(result.modified_count)
+    }
+
+    /// Modifies the first document matching `query` in `options.sort` order, returning it as it was
+    /// before or after the change, projected. Returns `None` when there was no such document to
+    /// return.
+    #[allow(clippy::too_many_arguments)]
+    pub fn find_and_modify(
+        &mut self,
+        database: String,
+        collection: String,
+        query: Document,
+        modification: Modification,
+        options: FindAndModifyOptions,
+        transaction: Option<Uuid>,
+    ) -> Result<Option<Document>, crate::Error> {
+        self.writable(&database)?;
+        // A replace deletes and reinserts the document, and an upsert may insert and then update
+        // it, so outside a transaction they get their own.
+        let several = match &modification {
+            Modification::Replace(_) => true,
+            Modification::Update(_) => options.upsert,
+            Modification::Delete => false,
+        };
+        if transaction.is_none() && several {
+            return self.within_transaction(&database.clone(), |daemon, id| {
+                daemon.find_and_modify(database, collection, query, modification, options, Some(id))
+            });
+        }
+        let projection = options
+            .projection
+            .as_ref()
+            .map(Projection::parse)
+            .transpose()?;
+        let coll = self.get_collection(database.clone(), collection.clone(), transaction)?;
+        let find = FindOptions {
+            sort: options.sort.clone(),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let before = coll.query(query.clone(), &find)?.next().transpose()?;
+        let id = before.as_ref().and_then(|d| d.get("_id").cloned());
+        let existing = UpdateOptions {
+            upsert: Some(false),
+        };
+        let (operation, after) = match (modification, id) {
+            (Modification::Delete, Some(id)) => {
+                coll.delete_one(doc! {"_id": id})?;
+                (ChangeOperation::Delete, None)
+            }
+            (Modification::Update(update), Some(id)) => {
+                let (_, update) = split_set_on_insert(update)?;
+                coll.update_one_with_options(doc! {"_id": id.clone()}, update, existing)?;
+                (ChangeOperation::Update, coll.find_one(doc! {"_id": id})?)
+            }
+            (Modification::Replace(replacement), Some(id)) => (
+                ChangeOperation::Update,
+                Some(coll.replace(&id, replacement)?),
+            ),
+            // An upsert that matched nothing inserts a document instead.
+            (Modification::Update(update), None) if options.upsert => {
+                // The update may change the fields the query matched on, so the inserted document
+                // is looked up by its `_id`.
+                let id = coll.upsert(&query, update)?;
+                (ChangeOperation::Insert, coll.find_one(doc! {"_id": id})?)
+            }
+            (Modification::Replace(replacement), None) if options.upsert => {
+                let id = coll
+                    .insert_many(&[replacement])?
+                    .inserted_ids
+                    .remove(&0)
+                    .unwrap_or(Bson::Null);
+                (ChangeOperation::Insert, coll.find_one(doc! {"_id": id})?)
+            }
+            _ => return Ok(None),
+        };
+        let ids = after
+            .as_ref()
+            .or(before.as_ref())
+            .and_then(|d| d.get("_id").cloned())
+            .into_iter()
+            .collect::<Vec<Bson>>();
+        let documents = match operation {
+            ChangeOperation::Delete => None,
+            _ => Some(after.clone().into_iter().collect()),
+        };
+        self.publish(
+            transaction,
+            ChangeRecord::new(&database, &collection, operation, ids, documents),
+        );
+        let returned = match (operation, options.return_document) {
+            (ChangeOperation::Delete, _) | (_, ReturnDocument::Before) => before,
+            (_, ReturnDocument::After) => after,
+        };
+        Ok(returned.map(|d| match &projection {
+            Some(p) => p.apply(d),
+            None => d,
+        }))
+    }
+
+    /// Replaces the first document matching `query` with `replacement`, keeping its `_id`. Returns
+    /// how many documents were replaced; an upsert that inserts instead replaces none.
+    pub fn replace_one(
+        &mut self,
+        database: String,
+        collection: String,
+        query: Document,
+        replacement: Document,
+        upsert: bool,
+        transaction: Option<Uuid>,
+    ) -> Result<u64, crate::Error> {
+        let options = FindAndModifyOptions {
+            projection: Some(doc! {"_id": 1}),
+            upsert,
+            ..Default::default()
+        };
+        self.find_and_modify(
+            database,
+            collection,
+            query,
+            Modification::Replace(replacement),
+            options,
+            transaction,
+        )
+        .map(|replaced| replaced.map_or(0, |_| 1))
+    }
+
+    /// Drops a collection along with its recorded indexes.
+    pub fn drop_collection(
+        &mut self,
+        database: String,
+        collection: String,
+        transaction: Option<Uuid>,
+    ) -> Result<(), crate::Error> {
+        self.writable(&database)?;
+        let target = self.get_collection(database.clone(), collection.clone(), transaction)?;
+        let registry =
+            self.get_collection(database.clone(), INDEX_REGISTRY.to_string(), transaction)?;
+        target
+            .drop()
+            .and_then(|_| registry.delete_many(doc! {"collection": &collection}))
+            .map_err(crate::Error::from)?;
+        self.publish(
+            transaction,
+            ChangeRecord::new(
+                &database,
+                &collection,
+                ChangeOperation::Drop,
+                Vec::new(),
+                None,
+            ),
+        );
+        Ok(())
+    }
+
+    pub fn open_cursor(
+        &mut self,
+        database: String,
+        collection: String,
+        query: Document,
+        options: FindOptions,
+        transaction: Option<Uuid>,
+    ) -> Result<Uuid, crate::Error> {
+        let projection = options.projection()?;
+        let cursor = self
+            .get_collection(database.clone(), collection, transaction)?
+            .query(query, &options)
+            .map_err(crate::Error::from)?;
+        let id = Uuid::new_v4();
+        self.cursors.insert(
+            id,
+            OpenCursor {
+                database,
+                cursor,
+                projection,
+                last_used: Instant::now(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Reads up to `size` documents from a cursor, closing it once it runs out.
+    pub fn next_batch(
+        &mut self,
+        cursor: Uuid,
+        size: usize,
+    ) -> Result<CursorBatch<Document>, crate::Error> {
+        let open = self
+            .cursors
+            .get_mut(&cursor)
+            .ok_or(crate::Error::UnknownCursor {
+                cursor: cursor.to_string(),
+            })?;
+        open.last_used = Instant::now();
+
+        let mut documents = Vec::with_capacity(size);
+        let mut exhausted = false;
+        while documents.len() < size {
+            match open.cursor.next() {
+                Some(Ok(document)) => documents.push(match &open.projection {
+                    Some(p) => p.apply(document),
+                    None => document,
+                }),
+                Some(Err(e)) => {
+                    self.cursors.remove(&cursor);
+                    return Err(e.into());
+                }
+                None => {
+                    exhausted = true;
+                    break;
+                }
+            }
+        }
+
+        if exhausted {
+            self.cursors.remove(&cursor);
+        }
+        Ok(CursorBatch {
+            documents,
+            exhausted,
+        })
+    }
+
+    pub fn close_cursor(&mut self, cursor: Uuid) -> Result<(), crate::Error> {
+        match self.cursors.remove(&cursor) {
+            Some(_) => Ok(()),
+            None => Err(crate::Error::UnknownCursor {
+                cursor: cursor.to_string(),
+            }),
+        }
+    }
+
+    /// Closes every cursor that has been idle for longer than `timeout`.
+    pub fn expire_cursors(&mut self, timeout: Duration) {
+        self.cursors.retain(|_, c| c.last_used.elapsed() <= timeout);
+    }
+
+    pub fn create_index(
+        &mut self,
+        database: String,
+        collection: String,
+        index: IndexDefinition,
+        transaction: Option<Uuid>,
+    ) -> Result<String, crate::Error> {
+        self.writable(&database)?;
+        let name = index.resolved_name()?;
+        let target = self.get_collection(database.clone(), collection.clone(), transaction)?;
+        let registry = self.get_collection(database, INDEX_REGISTRY.to_string(), transaction)?;
+        // Leave the registry untouched when it already records this exact index.
+        let recorded = registry
+            .find_one(doc! {"collection": &collection, "name": &name})?
+            .and_then(|d| from_document::<IndexDefinition>(d).ok());
+        if recorded.is_some_and(|r| r.keys == index.keys && r.unique == index.unique) {
+            return Ok(name);
+        }
+        target
+            .create_index(index.model())
+            .and_then(|_| registry.delete_many(doc! {"collection": &collection, "name": &name}))
+            .and_then(|_| {
+                registry.insert_many(&[doc! {
+                    "collection": &collection,
+                    "name": &name,
+                    "keys": index.keys,
+                    "unique": index.unique,
+                }])
+            })
+            .map_err(crate::Error::from)?;
+        Ok(name)
+    }
+
+    pub fn drop_index(
+        &mut self,
+        database: String,
+        collection: String,
+        name: String,
+        transaction: Option<Uuid>,
+    ) -> Result<(), crate::Error> {
+        self.writable(&database)?;
+        let target = self.get_collection(database.clone(), collection.clone(), transaction)?;
+        let registry = self.get_collection(database, INDEX_REGISTRY.to_string(), transaction)?;
+        target
+            .drop_index(&name)
+            .and_then(|_| registry.delete_many(doc! {"collection": &collection, "name": &name}))
+            .map(|_| ())
+            .map_err(crate::Error::from)
+    }
+
+    pub fn list_indexes(
+        &mut self,
+        database: String,
+        collection: String,
+    ) -> Result<Vec<IndexDefinition>, crate::Error> {
+        self.get_collection(database, INDEX_REGISTRY.to_string(), None)?
+            .find(doc! {"collection": &collection})
+            .run()
+            .and_then(|s| s.collect::<Result<Vec<Document>, _>>())
+            .map_err(crate::Error::from)?
+            .into_iter()
+            .map(|d| from_document::<IndexDefinition>(d).map_err(crate::Error::from))
+            .collect()
     }
 
     pub fn get_database_collections(&self, database: String) -> Result<Vec<String>, crate::Error> {
         match self.databases.get(&database) {
             Some(locked) => locked
                 .lock()
-                .or(Err(crate::Error::Sync(
-                    "Failed to acquire DB lock".to_string(),
-                )))
+                .or(Err(crate::Error::sync("Failed to acquire DB lock")))
                 .and_then(|d| d.collections()),
-            None => Err(crate::Error::UnknownDatabase("Invalid DB key".to_string())),
+            None => Err(crate::Error::unknown_database(&database)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use polodb_core::bson::doc;
+
+    use super::{
+        split_set_on_insert, upsert_seed, CountSelect, FindAndModifyOptions, KnownDatabases,
+        Modification, PoloDaemon, ReturnDocument,
+    };
+    use crate::changes::ChangeHub;
+
+    fn daemon() -> PoloDaemon {
+        let mut daemon = PoloDaemon::new(
+            Default::default(),
+            Vec::new(),
+            Arc::new(Mutex::new(KnownDatabases::load(None))),
+            async_channel::unbounded().0,
+            ChangeHub::default(),
+        );
+        daemon.open_memory("test").unwrap();
+        daemon
+    }
+
+    #[test]
+    fn upsert_seeds_equality_conditions_only() {
+        let query = doc! { "age": { "$gt": 3 }, "name": { "$eq": "ada" }, "$or": [{ "a": 1 }] };
+        let on_insert = doc! { "profile.city": "London" };
+        assert_eq!(
+            upsert_seed(&query, on_insert).unwrap(),
+            doc! { "name": "ada", "profile": { "city": "London" } }
+        );
+    }
+
+    #[test]
+    fn upsert_splits_off_set_on_insert() {
+        let update = doc! { "$set": { "a": 1 }, "$setOnInsert": { "b": 2 } };
+        assert_eq!(
+            split_set_on_insert(update).unwrap(),
+            (doc! { "b": 2 }, doc! { "$set": { "a": 1 } })
+        );
+        assert!(matches!(
+            split_set_on_insert(doc! { "$setOnInsert": 1 }),
+            Err(crate::Error::InvalidUpdate { .. })
+        ));
+    }
+
+    #[test]
+    fn upsert_applies_the_whole_update() {
+        let mut daemon = daemon();
+        let update = doc! {
+            "$set": { "status": "done" },
+            "$inc": { "visits": 1 },
+            "$push": { "tags": "new" },
+            "$setOnInsert": { "created": true },
+        };
+        daemon
+            .update(
+                "test".to_string(),
+                "users".to_string(),
+                doc! { "name": "ada", "status": "new" },
+                update,
+                CountSelect::One,
+                true,
+                None,
+            )
+            .unwrap();
+        let document = daemon
+            .get_collection("test".to_string(), "users".to_string(), None)
+            .unwrap()
+            .find_one(doc! { "name": "ada" })
+            .unwrap()
+            .unwrap();
+        assert_eq!(document.get_str("status").unwrap(), "done");
+        assert_eq!(document.get_i32("visits").unwrap(), 1);
+        assert_eq!(document.get_array("tags").unwrap().len(), 1);
+        assert!(document.get_bool("created").unwrap());
+    }
+
+    #[test]
+    fn find_and_modify_returns_the_upserted_document() {
+        let mut daemon = daemon();
+        let options = FindAndModifyOptions {
+            upsert: true,
+            return_document: ReturnDocument::After,
+            ..Default::default()
+        };
+        let found = daemon
+            .find_and_modify(
+                "test".to_string(),
+                "counters".to_string(),
+                doc! { "name": "visits" },
+                Modification::Update(doc! { "$inc": { "count": 1 } }),
+                options,
+                None,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.get_str("name").unwrap(), "visits");
+        assert_eq!(found.get_i32("count").unwrap(), 1);
+        assert!(found.contains_key("_id"));
+    }
+
+    #[test]
+    fn upsert_with_an_unknown_operator_inserts_nothing() {
+        let mut daemon = daemon();
+        let result = daemon.update(
+            "test".to_string(),
+            "users".to_string(),
+            doc! { "name": "ada" },
+            doc! { "$addToSet": { "tags": "new" } },
+            CountSelect::One,
+            true,
+            None,
+        );
+        assert!(result.is_err());
+        let found = daemon
+            .get_collection("test".to_string(), "users".to_string(), None)
+            .unwrap()
+            .find_one(doc! { "name": "ada" })
+            .unwrap();
+        assert!(found.is_none());
+    }
+}
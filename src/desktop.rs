@@ -1,31 +1,354 @@
-use polodb_core::bson::{doc, to_document};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_channel::{bounded, unbounded};
+use polodb_core::bson::{doc, Bson, Document};
 use serde::{de::DeserializeOwned, Serialize};
-use tauri::{plugin::PluginApi, AppHandle, Runtime};
+use serde_json::Value;
+use tauri::{ipc::Channel, utils::config::FsScope, AppHandle, Manager, Runtime};
+use uuid::Uuid;
+
+use crate::{
+    changes::{ChangeFilter, ChangeHub, ChangeRecord, ChangeSink},
+    config::{Config, DatabaseDeclaration},
+    daemon::{
+        messages::{CountSelect, Modification, PoloCommand, PoloManager},
+        CursorBatch, DaemonStatus, FindAndModifyOptions, FindOptions, IndexDefinition,
+        RequestOptions, SerializedDatabase, KNOWN_DATABASES,
+    },
+    error::{to_argument, to_arguments},
+    handle::Database,
+    live::{LiveQuery, LiveQueryOptions, LiveQueryUpdate},
+    scope::{configured_allows, resolve_path, Handle},
+};
+
+pub fn init<R: Runtime>(app: &AppHandle<R>, config: Config) -> Result<Polodb<R>, crate::Error> {
+    let directory = match &config.directory {
+        Some(directory) => {
+            let directory = resolve_path(app, None, directory)?;
+            std::fs::create_dir_all(&directory)?;
+            Some(directory)
+        }
+        None => None,
+    };
+    let (changes_tx, changes_rx) = unbounded::<ChangeRecord>();
+    let changes = ChangeHub::default();
 
-use crate::daemon::messages::{CountSelect, PoloCommand, PoloManager};
+    // Changes only go to subscribers, whose filters were checked against their window's scope.
+    let hub = changes.clone();
+    tauri::async_runtime::spawn(async move {
+        while let Ok(record) = changes_rx.recv().await {
+            hub.dispatch(&record);
+        }
+    });
 
-pub fn init<R: Runtime, C: DeserializeOwned>(
-    app: &AppHandle<R>,
-    _api: PluginApi<R, C>,
-) -> Result<Polodb<R>, ()> {
-    Ok(Polodb {
+    let polodb = Polodb {
         app: app.clone(),
-        api: PoloManager::new(),
-    })
+        api: PoloManager::new(
+            config.daemon,
+            config.indexes,
+            app.path()
+                .app_data_dir()
+                .ok()
+                .map(|d| d.join(KNOWN_DATABASES)),
+            changes_tx,
+            changes.clone(),
+        ),
+        changes,
+        scope: config.scope,
+        directory,
+        transaction: None,
+        request: RequestOptions::default(),
+    };
+    // A declared database that fails to open is reported by `daemon_status` rather than failing
+    // setup, so the app and its other databases keep working.
+    for declaration in config.databases {
+        let key = declaration.key.clone();
+        if let Err(e) = polodb.open_declared(declaration) {
+            polodb.api.record_failure(&key, e);
+        }
+    }
+    if let Err(e) = tauri::async_runtime::block_on(polodb.restore_databases()) {
+        polodb.api.record_failure(KNOWN_DATABASES, e);
+    }
+    Ok(polodb)
 }
 
 /// Access to the polodb APIs.
+///
+/// When `transaction` is set, every data operation runs inside that transaction instead of autocommitting.
+/// `request` tags every call with an id it can be cancelled by and the time to wait for it.
 pub struct Polodb<R: Runtime> {
     pub app: AppHandle<R>,
     pub api: PoloManager,
+    pub changes: ChangeHub,
+    /// Database paths the frontend may open, as configured in `tauri.conf.json`.
+    pub scope: FsScope,
+    /// Directory relative database paths are resolved against.
+    pub directory: Option<PathBuf>,
+    pub transaction: Option<Uuid>,
+    pub request: RequestOptions,
+}
+
+impl<R: Runtime> Clone for Polodb<R> {
+    fn clone(&self) -> Self {
+        Polodb {
+            app: self.app.clone(),
+            api: self.api.clone(),
+            changes: self.changes.clone(),
+            scope: self.scope.clone(),
+            directory: self.directory.clone(),
+            transaction: self.transaction,
+            request: self.request.clone(),
+        }
+    }
 }
 
 impl<R: Runtime> Polodb<R> {
+    /// Opens a database declared in the plugin configuration.
+    fn open_declared(&self, declaration: DatabaseDeclaration) -> Result<(), crate::Error> {
+        let path = resolve_path(&self.app, self.directory.as_deref(), &declaration.path)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let path = path.to_str().ok_or(crate::Error::io("Invalid path"))?;
+        tauri::async_runtime::block_on(self.open(declaration.key, path, declaration.read_only))?;
+        Ok(())
+    }
+
     pub async fn call<T: Serialize + DeserializeOwned>(
         &self,
         command: PoloCommand,
     ) -> Result<T, crate::Error> {
-        self.api.call::<T>(command).await
+        self.api.call_with::<T>(command, &self.request).await
+    }
+
+    /// Returns a copy of this handle whose operations run inside `transaction` (or autocommit if `None`).
+    pub fn with_transaction(&self, transaction: Option<Uuid>) -> Polodb<R> {
+        Polodb {
+            transaction,
+            ..self.clone()
+        }
+    }
+
+    pub async fn begin_transaction<T: AsRef<str>>(
+        &self,
+        database: T,
+        owner: Option<String>,
+    ) -> Result<Uuid, crate::Error> {
+        self.call::<Uuid>(PoloCommand::BeginTransaction {
+            database: database.as_ref().to_string(),
+            owner,
+        })
+        .await
+    }
+
+    pub async fn commit_transaction(&self, transaction: Uuid) -> Result<(), crate::Error> {
+        self.call::<()>(PoloCommand::CommitTransaction(transaction))
+            .await
+    }
+
+    pub async fn abort_transaction(&self, transaction: Uuid) -> Result<(), crate::Error> {
+        self.call::<()>(PoloCommand::AbortTransaction(transaction))
+            .await
+    }
+
+    /// Finishes queued commands, rolls back open transactions and closes every database. Called
+    /// automatically when the app exits; any command sent afterwards fails.
+    pub fn shutdown(&self) -> Result<(), crate::Error> {
+        self.api.shutdown()
+    }
+
+    /// Reports the daemon's uptime, how often a worker recovered from a panic and how much work is
+    /// queued for each open database.
+    pub fn daemon_status(&self) -> DaemonStatus {
+        self.api.status()
+    }
+
+    /// Returns a handle to the database `key`, from which typed collection handles can be taken.
+    /// The database is not checked to be open until the handle is used.
+    pub fn database<T: AsRef<str>>(&self, key: T) -> Database<R> {
+        Database::new(self.clone(), key.as_ref().to_string())
+    }
+
+    /// Returns a copy of this handle whose calls are sent with `request`.
+    pub fn with_request(&self, request: RequestOptions) -> Polodb<R> {
+        Polodb {
+            request,
+            ..self.clone()
+        }
+    }
+
+    /// Abandons the pending call sent with `request` as its id, which then fails with
+    /// [`crate::Error::Cancelled`]. Returns whether such a call was pending.
+    pub fn cancel(&self, request: Uuid) -> bool {
+        self.api.cancel(request)
+    }
+
+    /// Who opened the transaction, cursor, subscription, live query or pending call `id`.
+    pub(crate) fn handle(&self, id: Uuid) -> Option<Handle> {
+        self.api.handle(&id).or_else(|| self.changes.handle(&id))
+    }
+
+    /// Abandons every pending call sent by `owner` (a webview label), returning how many there were.
+    pub fn cancel_requests<T: AsRef<str>>(&self, owner: T) -> usize {
+        self.api.cancel_owned(owner.as_ref())
+    }
+
+    /// Rolls back every open transaction begun by `owner` (a window label).
+    pub async fn release_transactions<T: AsRef<str>>(
+        &self,
+        owner: T,
+    ) -> Result<usize, crate::Error> {
+        self.call::<usize>(PoloCommand::ReleaseTransactions {
+            owner: owner.as_ref().to_string(),
+        })
+        .await
+    }
+
+    /// Runs `operation` inside a new transaction on `database`.
+    ///
+    /// The handle passed to `operation` routes every call through the transaction. It is committed if
+    /// `operation` returns `Ok`, and rolled back if it returns `Err`.
+    pub async fn transaction<T, Db, F, Fut>(
+        &self,
+        database: Db,
+        operation: F,
+    ) -> Result<T, crate::Error>
+    where
+        Db: AsRef<str>,
+        F: FnOnce(Polodb<R>) -> Fut,
+        Fut: Future<Output = Result<T, crate::Error>>,
+    {
+        let id = self.begin_transaction(database, None).await?;
+        match operation(self.with_transaction(Some(id))).await {
+            Ok(result) => {
+                self.commit_transaction(id).await?;
+                Ok(result)
+            }
+            Err(e) => {
+                let _ = self.abort_transaction(id).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Calls `callback` with every committed change that matches `filter`.
+    pub fn subscribe<F: Fn(&ChangeRecord) + Send + Sync + 'static>(
+        &self,
+        filter: ChangeFilter,
+        callback: F,
+    ) -> Result<Uuid, crate::Error> {
+        self.changes
+            .subscribe(filter, None, ChangeSink::Callback(Arc::new(callback)))
+    }
+
+    /// Streams every committed change that matches `filter` to a webview channel owned by `owner`.
+    pub fn subscribe_channel(
+        &self,
+        filter: ChangeFilter,
+        owner: Option<String>,
+        channel: Channel<ChangeRecord>,
+    ) -> Result<Uuid, crate::Error> {
+        self.changes
+            .subscribe(filter, owner, ChangeSink::Channel(channel))
+    }
+
+    pub fn unsubscribe(&self, subscription: Uuid) -> Result<(), crate::Error> {
+        self.changes.unsubscribe(subscription)
+    }
+
+    /// Drops every change subscription made by `owner`, returning how many were removed.
+    pub fn release_subscriptions<T: AsRef<str>>(&self, owner: T) -> usize {
+        self.changes.release(owner)
+    }
+
+    /// Runs `query` and calls `on_update` whenever a committed write changes its results, until the
+    /// returned id is passed to [`Polodb::unsubscribe`] or `on_update` returns `false`. A re-run
+    /// that fails is reported as a [`LiveQueryUpdate::Error`].
+    pub async fn watch_query<
+        Query: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+        F: Fn(LiveQueryUpdate) -> bool + Send + 'static,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+        options: LiveQueryOptions,
+        owner: Option<String>,
+        on_update: F,
+    ) -> Result<LiveQuery, crate::Error> {
+        let query = to_argument("query", &query)?;
+        let database = database.as_ref().to_string();
+        let collection = collection.as_ref().to_string();
+
+        // Bursts of writes collapse into a single pending re-run.
+        let (rerun_tx, rerun_rx) = bounded::<()>(1);
+        let id = self.changes.subscribe(
+            ChangeFilter {
+                database: Some(database.clone()),
+                collection: Some(collection.clone()),
+                ..Default::default()
+            },
+            owner,
+            ChangeSink::Callback(Arc::new(move |_| {
+                let _ = rerun_tx.try_send(());
+            })),
+        )?;
+
+        let polodb = self.with_transaction(None);
+        let documents = match polodb
+            .find_with_options::<Value, Document, _, _>(
+                &database,
+                &collection,
+                query.clone(),
+                options.find.clone(),
+            )
+            .await
+        {
+            Ok(documents) => documents,
+            Err(e) => {
+                let _ = self.changes.unsubscribe(id);
+                return Err(e);
+            }
+        };
+
+        let mut previous = documents.clone();
+        tauri::async_runtime::spawn(async move {
+            while rerun_rx.recv().await.is_ok() {
+                let (update, current) = match polodb
+                    .find_with_options::<Value, Document, _, _>(
+                        &database,
+                        &collection,
+                        query.clone(),
+                        options.find.clone(),
+                    )
+                    .await
+                {
+                    Ok(current) => (
+                        LiveQueryUpdate::between(&previous, &current, options.mode),
+                        Some(current),
+                    ),
+                    Err(error) => (Some(LiveQueryUpdate::Error { error }), None),
+                };
+                if let Some(update) = update {
+                    if !on_update(update) {
+                        let _ = polodb.changes.unsubscribe(id);
+                        break;
+                    }
+                }
+                if let Some(current) = current {
+                    previous = current;
+                }
+            }
+        });
+
+        Ok(LiveQuery { id, documents })
     }
 
     pub async fn open_database<T: AsRef<str>, P: AsRef<str>>(
@@ -33,35 +356,114 @@ impl<R: Runtime> Polodb<R> {
         key: T,
         path: P,
     ) -> Result<String, crate::Error> {
-        self.api
-            .call::<String>(PoloCommand::OpenDatabase {
-                key: key.as_ref().to_string(),
-                path: path.as_ref().to_string(),
-            })
+        self.open(key, path, false).await
+    }
+
+    /// Opens a database that rejects every write with [`crate::Error::ReadOnly`].
+    pub async fn open_database_read_only<T: AsRef<str>, P: AsRef<str>>(
+        &self,
+        key: T,
+        path: P,
+    ) -> Result<String, crate::Error> {
+        self.open(key, path, true).await
+    }
+
+    /// Opens a scratch database under `key` that is discarded when it is closed, unless it is saved
+    /// to a file first with [`Polodb::save_as`].
+    pub async fn open_memory_database<T: AsRef<str>>(
+        &self,
+        key: T,
+    ) -> Result<String, crate::Error> {
+        self.call::<String>(PoloCommand::OpenMemoryDatabase(key.as_ref().to_string()))
+            .await
+    }
+
+    /// Persists the in-memory database `key` to a new database at `path`, which takes its place.
+    pub async fn save_as<T: AsRef<str>, P: AsRef<str>>(
+        &self,
+        key: T,
+        path: P,
+    ) -> Result<String, crate::Error> {
+        self.call::<String>(PoloCommand::SaveAs {
+            key: key.as_ref().to_string(),
+            path: path.as_ref().to_string(),
+        })
+        .await
+    }
+
+    async fn open<T: AsRef<str>, P: AsRef<str>>(
+        &self,
+        key: T,
+        path: P,
+        read_only: bool,
+    ) -> Result<String, crate::Error> {
+        self.call::<String>(PoloCommand::OpenDatabase {
+            key: key.as_ref().to_string(),
+            path: path.as_ref().to_string(),
+            read_only,
+        })
+        .await
+    }
+
+    /// Lists the databases that will be reopened on the next launch, whether or not they are open now.
+    pub async fn list_known_databases(&self) -> Result<Vec<SerializedDatabase>, crate::Error> {
+        self.call::<Vec<SerializedDatabase>>(PoloCommand::ListKnownDatabases)
             .await
     }
 
+    /// Stops `key` from being reopened on the next launch. Returns whether it was known; an open
+    /// database stays open.
+    pub async fn forget_database<T: AsRef<str>>(&self, key: T) -> Result<bool, crate::Error> {
+        self.call::<bool>(PoloCommand::ForgetDatabase(key.as_ref().to_string()))
+            .await
+    }
+
+    /// Reopens every known database that is not open yet and whose file the configured scope still
+    /// allows, returning the keys that were restored. Databases outside the scope are skipped, and
+    /// those that fail to open are listed under `failed` in [`Polodb::daemon_status`]; both stay known.
+    pub async fn restore_databases(&self) -> Result<Vec<String>, crate::Error> {
+        let open = self.list_databases().await?;
+        let mut restored = Vec::new();
+        for database in self.list_known_databases().await? {
+            if open.contains(&database.key) {
+                continue;
+            }
+            let key = database.key.clone();
+            let result = match configured_allows(&self.app, &self.scope, Path::new(&database.file))
+            {
+                Ok(true) => {
+                    self.call::<()>(PoloCommand::RestoreDatabase(database))
+                        .await
+                }
+                Ok(false) => continue,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => restored.push(key),
+                Err(e) => self.api.record_failure(&key, e),
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Closes `key` and forgets it, so it is not reopened on the next launch.
     pub async fn close_database<T: AsRef<str>>(&self, key: T) -> Result<String, crate::Error> {
-        self.api
-            .call::<String>(PoloCommand::CloseDatabase(key.as_ref().to_string()))
+        self.call::<String>(PoloCommand::CloseDatabase(key.as_ref().to_string()))
             .await
     }
 
     pub async fn list_databases(&self) -> Result<Vec<String>, crate::Error> {
-        self.api
-            .call::<Vec<String>>(PoloCommand::ListDatabases)
-            .await
+        self.call::<Vec<String>>(PoloCommand::ListDatabases).await
     }
 
     pub async fn list_collections<T: AsRef<str>>(
         &self,
         database: T,
     ) -> Result<Vec<String>, crate::Error> {
-        self.api
-            .call::<Vec<String>>(PoloCommand::ListCollections {
-                database: database.as_ref().to_string(),
-            })
-            .await
+        self.call::<Vec<String>>(PoloCommand::ListCollections {
+            database: database.as_ref().to_string(),
+        })
+        .await
     }
 
     pub async fn insert<Doc: Serialize + DeserializeOwned, Db: AsRef<str>, Coll: AsRef<str>>(
@@ -69,14 +471,28 @@ impl<R: Runtime> Polodb<R> {
         database: Db,
         collection: Coll,
         documents: Vec<Doc>,
-    ) -> Result<Vec<usize>, crate::Error> {
-        self.api
-            .call::<Vec<usize>>(PoloCommand::Insert {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                value: documents.iter().map(|d| to_document(d).unwrap()).collect(),
+    ) -> Result<Vec<Bson>, crate::Error> {
+        self.call::<Vec<Bson>>(PoloCommand::Insert {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            value: to_arguments("documents", &documents)?,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    pub async fn insert_one<Doc: Serialize + DeserializeOwned, Db: AsRef<str>, Coll: AsRef<str>>(
+        &self,
+        database: Db,
+        collection: Coll,
+        document: Doc,
+    ) -> Result<Bson, crate::Error> {
+        self.insert(database, collection, vec![document])
+            .await?
+            .pop()
+            .ok_or(crate::Error::Database {
+                message: "No document was inserted".to_string(),
             })
-            .await
     }
 
     pub async fn find<
@@ -90,15 +506,15 @@ impl<R: Runtime> Polodb<R> {
         collection: Coll,
         query: Query,
     ) -> Result<Vec<Doc>, crate::Error> {
-        self.api
-            .call::<Vec<Doc>>(PoloCommand::Find {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: to_document(&query).unwrap(),
-                count: CountSelect::Many,
-                sort: None,
-            })
-            .await
+        self.call::<Vec<Doc>>(PoloCommand::Find {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            count: CountSelect::Many,
+            options: FindOptions::default(),
+            transaction: self.transaction,
+        })
+        .await
     }
 
     pub async fn find_sorted<
@@ -114,15 +530,15 @@ impl<R: Runtime> Polodb<R> {
         query: Query,
         sort: Sort,
     ) -> Result<Vec<Doc>, crate::Error> {
-        self.api
-            .call::<Vec<Doc>>(PoloCommand::Find {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: to_document(&query).unwrap(),
-                count: CountSelect::Many,
-                sort: Some(to_document(&sort).unwrap()),
-            })
-            .await
+        self.call::<Vec<Doc>>(PoloCommand::Find {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            count: CountSelect::Many,
+            options: FindOptions::sorted(to_argument("sort", &sort)?),
+            transaction: self.transaction,
+        })
+        .await
     }
 
     pub async fn find_one<
@@ -135,16 +551,36 @@ impl<R: Runtime> Polodb<R> {
         database: Db,
         collection: Coll,
         query: Query,
-    ) -> Result<Doc, crate::Error> {
-        self.api
-            .call::<Doc>(PoloCommand::Find {
+    ) -> Result<Option<Doc>, crate::Error> {
+        self.find_one_with_options(database, collection, query, FindOptions::default())
+            .await
+    }
+
+    /// Returns the first document matching `query` in `options.sort` order, or `None` if nothing matches.
+    /// `skip` and `projection` are honoured, `limit` is ignored.
+    pub async fn find_one_with_options<
+        Doc: Serialize + DeserializeOwned,
+        Query: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+        options: FindOptions,
+    ) -> Result<Option<Doc>, crate::Error> {
+        Ok(self
+            .call::<Vec<Doc>>(PoloCommand::Find {
                 database: database.as_ref().to_string(),
                 collection: collection.as_ref().to_string(),
-                query: to_document(&query).unwrap(),
+                query: to_argument("query", &query)?,
                 count: CountSelect::One,
-                sort: None,
+                options,
+                transaction: self.transaction,
             })
-            .await
+            .await?
+            .pop())
     }
 
     pub async fn all<Doc: Serialize + DeserializeOwned, Db: AsRef<str>, Coll: AsRef<str>>(
@@ -152,15 +588,15 @@ impl<R: Runtime> Polodb<R> {
         database: Db,
         collection: Coll,
     ) -> Result<Vec<Doc>, crate::Error> {
-        self.api
-            .call::<Vec<Doc>>(PoloCommand::Find {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: doc! {},
-                count: CountSelect::Many,
-                sort: None,
-            })
-            .await
+        self.call::<Vec<Doc>>(PoloCommand::Find {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: doc! {},
+            count: CountSelect::Many,
+            options: FindOptions::default(),
+            transaction: self.transaction,
+        })
+        .await
     }
 
     pub async fn all_sorted<
@@ -174,31 +610,231 @@ impl<R: Runtime> Polodb<R> {
         collection: Coll,
         sort: Sort,
     ) -> Result<Vec<Doc>, crate::Error> {
-        self.api
-            .call::<Vec<Doc>>(PoloCommand::Find {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: doc! {},
-                count: CountSelect::Many,
-                sort: Some(to_document(&sort).unwrap()),
-            })
+        self.call::<Vec<Doc>>(PoloCommand::Find {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: doc! {},
+            count: CountSelect::Many,
+            options: FindOptions::sorted(to_argument("sort", &sort)?),
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    pub async fn find_with_options<
+        Doc: Serialize + DeserializeOwned,
+        Query: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+        options: FindOptions,
+    ) -> Result<Vec<Doc>, crate::Error> {
+        self.call::<Vec<Doc>>(PoloCommand::Find {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            count: CountSelect::Many,
+            options,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    pub async fn all_with_options<
+        Doc: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        options: FindOptions,
+    ) -> Result<Vec<Doc>, crate::Error> {
+        self.find_with_options(database, collection, doc! {}, options)
             .await
     }
 
+    /// Opens a cursor over the results of `query`, to be read with [`Polodb::next_batch`].
+    pub async fn open_cursor<
+        Query: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+        options: FindOptions,
+    ) -> Result<Uuid, crate::Error> {
+        self.call::<Uuid>(PoloCommand::OpenCursor {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            options,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    pub async fn next_batch<Doc: Serialize + DeserializeOwned>(
+        &self,
+        cursor: Uuid,
+        size: usize,
+    ) -> Result<CursorBatch<Doc>, crate::Error> {
+        self.call::<CursorBatch<Doc>>(PoloCommand::NextBatch { cursor, size })
+            .await
+    }
+
+    pub async fn close_cursor(&self, cursor: Uuid) -> Result<(), crate::Error> {
+        self.call::<()>(PoloCommand::CloseCursor(cursor)).await
+    }
+
+    /// Counts the documents matching `query` inside the daemon.
+    pub async fn count<Query: Serialize + DeserializeOwned, Db: AsRef<str>, Coll: AsRef<str>>(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+    ) -> Result<u64, crate::Error> {
+        self.call::<u64>(PoloCommand::Count {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    /// The number of documents in the collection, without filtering.
+    pub async fn estimated_count<Db: AsRef<str>, Coll: AsRef<str>>(
+        &self,
+        database: Db,
+        collection: Coll,
+    ) -> Result<u64, crate::Error> {
+        self.call::<u64>(PoloCommand::EstimatedCount {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    /// The distinct values of the dotted `field` across the documents matching `query`.
+    pub async fn distinct<
+        Query: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+        Field: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        field: Field,
+        query: Query,
+    ) -> Result<Vec<Bson>, crate::Error> {
+        self.call::<Vec<Bson>>(PoloCommand::Distinct {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            field: field.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    pub async fn aggregate<
+        Doc: Serialize + DeserializeOwned,
+        Stage: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        pipeline: Vec<Stage>,
+    ) -> Result<Vec<Doc>, crate::Error> {
+        self.call::<Vec<Doc>>(PoloCommand::Aggregate {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            pipeline: to_arguments("pipeline", &pipeline)?,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    pub async fn create_index<Db: AsRef<str>, Coll: AsRef<str>>(
+        &self,
+        database: Db,
+        collection: Coll,
+        index: IndexDefinition,
+    ) -> Result<String, crate::Error> {
+        self.call::<String>(PoloCommand::CreateIndex {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            index,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    pub async fn drop_index<Db: AsRef<str>, Coll: AsRef<str>, Name: AsRef<str>>(
+        &self,
+        database: Db,
+        collection: Coll,
+        name: Name,
+    ) -> Result<(), crate::Error> {
+        self.call::<()>(PoloCommand::DropIndex {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            name: name.as_ref().to_string(),
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    pub async fn list_indexes<Db: AsRef<str>, Coll: AsRef<str>>(
+        &self,
+        database: Db,
+        collection: Coll,
+    ) -> Result<Vec<IndexDefinition>, crate::Error> {
+        self.call::<Vec<IndexDefinition>>(PoloCommand::ListIndexes {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+        })
+        .await
+    }
+
+    pub async fn drop_collection<Db: AsRef<str>, Coll: AsRef<str>>(
+        &self,
+        database: Db,
+        collection: Coll,
+    ) -> Result<(), crate::Error> {
+        self.call::<()>(PoloCommand::DropCollection {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            transaction: self.transaction,
+        })
+        .await
+    }
+
     pub async fn delete<Query: Serialize + DeserializeOwned, Db: AsRef<str>, Coll: AsRef<str>>(
         &self,
         database: Db,
         collection: Coll,
         query: Query,
     ) -> Result<u64, crate::Error> {
-        self.api
-            .call::<u64>(PoloCommand::Delete {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: to_document(&query).unwrap(),
-                count: CountSelect::Many,
-            })
-            .await
+        self.call::<u64>(PoloCommand::Delete {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            count: CountSelect::Many,
+            transaction: self.transaction,
+        })
+        .await
     }
 
     pub async fn delete_one<
@@ -211,14 +847,14 @@ impl<R: Runtime> Polodb<R> {
         collection: Coll,
         query: Query,
     ) -> Result<u64, crate::Error> {
-        self.api
-            .call::<u64>(PoloCommand::Delete {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: to_document(&query).unwrap(),
-                count: CountSelect::One,
-            })
-            .await
+        self.call::<u64>(PoloCommand::Delete {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            count: CountSelect::One,
+            transaction: self.transaction,
+        })
+        .await
     }
 
     pub async fn delete_all<Db: AsRef<str>, Coll: AsRef<str>>(
@@ -226,14 +862,14 @@ impl<R: Runtime> Polodb<R> {
         database: Db,
         collection: Coll,
     ) -> Result<u64, crate::Error> {
-        self.api
-            .call::<u64>(PoloCommand::Delete {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: doc! {},
-                count: CountSelect::Many,
-            })
-            .await
+        self.call::<u64>(PoloCommand::Delete {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: doc! {},
+            count: CountSelect::Many,
+            transaction: self.transaction,
+        })
+        .await
     }
 
     pub async fn update<
@@ -249,16 +885,16 @@ impl<R: Runtime> Polodb<R> {
         update: Update,
         upsert: bool,
     ) -> Result<u64, crate::Error> {
-        self.api
-            .call::<u64>(PoloCommand::Update {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: to_document(&query).unwrap(),
-                update: to_document(&update).unwrap(),
-                upsert,
-                count: CountSelect::Many,
-            })
-            .await
+        self.call::<u64>(PoloCommand::Update {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            update: to_argument("update", &update)?,
+            upsert,
+            count: CountSelect::Many,
+            transaction: self.transaction,
+        })
+        .await
     }
 
     pub async fn update_one<
@@ -274,16 +910,16 @@ impl<R: Runtime> Polodb<R> {
         update: Update,
         upsert: bool,
     ) -> Result<u64, crate::Error> {
-        self.api
-            .call::<u64>(PoloCommand::Update {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: to_document(&query).unwrap(),
-                update: to_document(&update).unwrap(),
-                upsert,
-                count: CountSelect::One,
-            })
-            .await
+        self.call::<u64>(PoloCommand::Update {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            update: to_argument("update", &update)?,
+            upsert,
+            count: CountSelect::One,
+            transaction: self.transaction,
+        })
+        .await
     }
 
     pub async fn update_all<
@@ -297,15 +933,117 @@ impl<R: Runtime> Polodb<R> {
         update: Update,
         upsert: bool,
     ) -> Result<u64, crate::Error> {
-        self.api
-            .call::<u64>(PoloCommand::Update {
-                database: database.as_ref().to_string(),
-                collection: collection.as_ref().to_string(),
-                query: doc! {},
-                update: to_document(&update).unwrap(),
-                upsert,
-                count: CountSelect::Many,
-            })
-            .await
+        self.call::<u64>(PoloCommand::Update {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: doc! {},
+            update: to_argument("update", &update)?,
+            upsert,
+            count: CountSelect::Many,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    /// Replaces the first document matching `query` with `replacement`, keeping its `_id`.
+    pub async fn replace_one<
+        Doc: Serialize + DeserializeOwned,
+        Query: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+        replacement: Doc,
+        upsert: bool,
+    ) -> Result<u64, crate::Error> {
+        self.call::<u64>(PoloCommand::Replace {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            replacement: to_argument("replacement", &replacement)?,
+            upsert,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    /// Applies `update` to the first document matching `query` in `options.sort` order, in one step,
+    /// and returns the document as it was before or after.
+    pub async fn find_one_and_update<
+        Doc: Serialize + DeserializeOwned,
+        Query: Serialize + DeserializeOwned,
+        Update: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+        update: Update,
+        options: FindAndModifyOptions,
+    ) -> Result<Option<Doc>, crate::Error> {
+        self.call::<Option<Doc>>(PoloCommand::FindAndModify {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            modification: Modification::Update(to_argument("update", &update)?),
+            options,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    /// Replaces the first document matching `query` in `options.sort` order with `replacement`,
+    /// keeping its `_id`, and returns the document as it was before or after.
+    pub async fn find_one_and_replace<
+        Doc: Serialize + DeserializeOwned,
+        Query: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+        replacement: Doc,
+        options: FindAndModifyOptions,
+    ) -> Result<Option<Doc>, crate::Error> {
+        self.call::<Option<Doc>>(PoloCommand::FindAndModify {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            modification: Modification::Replace(to_argument("replacement", &replacement)?),
+            options,
+            transaction: self.transaction,
+        })
+        .await
+    }
+
+    /// Deletes the first document matching `query` in `options.sort` order and returns it.
+    pub async fn find_one_and_delete<
+        Doc: Serialize + DeserializeOwned,
+        Query: Serialize + DeserializeOwned,
+        Db: AsRef<str>,
+        Coll: AsRef<str>,
+    >(
+        &self,
+        database: Db,
+        collection: Coll,
+        query: Query,
+        options: FindAndModifyOptions,
+    ) -> Result<Option<Doc>, crate::Error> {
+        self.call::<Option<Doc>>(PoloCommand::FindAndModify {
+            database: database.as_ref().to_string(),
+            collection: collection.as_ref().to_string(),
+            query: to_argument("query", &query)?,
+            modification: Modification::Delete,
+            options,
+            transaction: self.transaction,
+        })
+        .await
     }
 }
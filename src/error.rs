@@ -1,13 +1,379 @@
-use serde::{Deserialize, Serialize};
+use polodb_core::bson::{to_bson, Bson, Document};
+use serde::{
+    de::{DeserializeOwned, Error as _},
+    ser::Error as _,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_json::Value;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Errors returned by the plugin.
+///
+/// Errors cross the IPC boundary as an object with a stable snake_case `code`, a human readable
+/// `message` and the variant's fields, e.g.
+/// `{"code": "duplicate_key", "message": "...", "collection": "users", "index": "email_1", "key": "..."}`.
+#[derive(Clone, Debug, thiserror::Error, Serialize, Deserialize)]
+#[serde(remote = "Self", tag = "code", rename_all = "snake_case")]
 pub enum Error {
-    Sync(String),
-    UnknownDatabase(String),
-    Io(String),
-    ExistingDatabase(String),
-    DatabaseError(String),
-    DaemonError(String),
-    SerializationError(String),
-    InsertError(String)
-}
\ No newline at end of file
+    #[error("internal lock failure: {context}")]
+    Sync { context: String },
+    #[error("daemon failure: {message}")]
+    Daemon { message: String },
+    #[error("command panicked: {message}")]
+    Panicked { message: String },
+    #[error("request timed out after {milliseconds}ms")]
+    Timeout { milliseconds: u64 },
+    #[error("request was cancelled")]
+    Cancelled,
+    #[error("too many commands are queued for database {database:?}")]
+    Busy { database: String },
+    #[error("unknown database {database:?}")]
+    UnknownDatabase { database: String },
+    #[error("database {database:?} is read-only")]
+    ReadOnly { database: String },
+    #[error("database is closed")]
+    Closed,
+    #[error("database is locked: {message}")]
+    Locked { message: String },
+    #[error("database is corrupted: {message}")]
+    Corrupted { message: String },
+    #[error("io error: {message}")]
+    Io { message: String },
+    #[error("database path {path:?} is outside the allowed scope")]
+    PathForbidden { path: String },
+    #[error("{access} access to {database}/{collection} is not permitted")]
+    PermissionDenied {
+        database: String,
+        collection: String,
+        access: String,
+    },
+    #[error("{what} not found")]
+    NotFound { what: String },
+    #[error("duplicate key in {collection} (index {index}): {key}")]
+    DuplicateKey {
+        collection: String,
+        index: String,
+        key: String,
+    },
+    #[error("invalid query: {message}")]
+    InvalidQuery { message: String },
+    #[error("invalid update: {message}")]
+    InvalidUpdate { message: String },
+    #[error("invalid index: {message}")]
+    InvalidIndex { message: String },
+    #[error("serialization failure: {message}")]
+    Serialization {
+        message: String,
+        /// The argument that failed to convert, when the failure is in a caller's input.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        argument: Option<String>,
+        /// Where in `argument` the failure is, e.g. the index of a document in a list.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    #[error("unknown transaction {transaction}")]
+    UnknownTransaction { transaction: String },
+    #[error("transaction failure: {message}")]
+    Transaction { message: String },
+    #[error("unknown cursor {cursor}")]
+    UnknownCursor { cursor: String },
+    #[error("unknown subscription {subscription}")]
+    UnknownSubscription { subscription: String },
+    #[error("database error: {message}")]
+    Database { message: String },
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut value =
+            Error::serialize(self, serde_json::value::Serializer).map_err(S::Error::custom)?;
+        if let Value::Object(fields) = &mut value {
+            fields.insert("message".to_string(), Value::String(self.to_string()));
+        }
+        value.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Error {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = Value::deserialize(deserializer)?;
+        if let Value::Object(fields) = &mut value {
+            // The message is derived from the other fields, so it is not read back.
+            fields.remove("message");
+        }
+        Error::deserialize(value).map_err(D::Error::custom)
+    }
+}
+
+impl Error {
+    pub fn sync<T: AsRef<str>>(context: T) -> Self {
+        Error::Sync {
+            context: context.as_ref().to_string(),
+        }
+    }
+
+    pub fn daemon<T: AsRef<str>>(message: T) -> Self {
+        Error::Daemon {
+            message: message.as_ref().to_string(),
+        }
+    }
+
+    /// Describes the payload of a caught panic.
+    pub fn panicked(panic: Box<dyn std::any::Any + Send>) -> Self {
+        let message = match panic.downcast::<String>() {
+            Ok(message) => *message,
+            Err(panic) => match panic.downcast::<&'static str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "unknown panic".to_string(),
+            },
+        };
+        Error::Panicked { message }
+    }
+
+    pub fn io<T: AsRef<str>>(message: T) -> Self {
+        Error::Io {
+            message: message.as_ref().to_string(),
+        }
+    }
+
+    pub fn serialization<T: ToString>(error: T) -> Self {
+        Error::Serialization {
+            message: error.to_string(),
+            argument: None,
+            path: None,
+        }
+    }
+
+    /// Reports that the argument `argument` (at `path` within it, if given) could not be converted.
+    pub fn argument<A: AsRef<str>, E: ToString>(
+        argument: A,
+        path: Option<String>,
+        error: E,
+    ) -> Self {
+        let location = match &path {
+            Some(path) => format!("{}[{}]", argument.as_ref(), path),
+            None => argument.as_ref().to_string(),
+        };
+        Error::Serialization {
+            message: format!("{}: {}", location, error.to_string()),
+            argument: Some(argument.as_ref().to_string()),
+            path,
+        }
+    }
+
+    pub fn invalid_query<T: AsRef<str>>(message: T) -> Self {
+        Error::InvalidQuery {
+            message: message.as_ref().to_string(),
+        }
+    }
+
+    pub fn unknown_database<T: AsRef<str>>(database: T) -> Self {
+        Error::UnknownDatabase {
+            database: database.as_ref().to_string(),
+        }
+    }
+}
+
+impl From<polodb_core::Error> for Error {
+    fn from(error: polodb_core::Error) -> Self {
+        use polodb_core::Error as E;
+        let message = error.to_string();
+        match error {
+            E::DuplicateKey(duplicate) => Error::DuplicateKey {
+                collection: duplicate.ns,
+                index: duplicate.name,
+                key: duplicate.key,
+            },
+            E::DataExist(key) => Error::DuplicateKey {
+                collection: String::new(),
+                index: "_id".to_string(),
+                key,
+            },
+            E::CollectionNotFound(collection) => Error::NotFound {
+                what: format!("collection {:?}", collection),
+            },
+            E::DbIsClosed | E::DbNotReady => Error::Closed,
+            E::Busy | E::DatabaseOccupied | E::LockError => Error::Locked { message },
+            E::IOErr(_) => Error::Io { message },
+            E::ChecksumMismatch
+            | E::JournalPageSizeMismatch(..)
+            | E::SaltMismatch
+            | E::PageMagicMismatch(_)
+            | E::UnexpectedPageHeader
+            | E::NotAValidDatabase
+            | E::VersionMismatch(_)
+            | E::DecodeEOF
+            | E::UnknownBsonElementType(_) => Error::Corrupted { message },
+            E::BsonErr(_) | E::BsonDeErr(_) | E::UTF8Err { .. } | E::FromUtf8Error(_) => {
+                Error::Serialization {
+                    message,
+                    argument: None,
+                    path: None,
+                }
+            }
+            E::UnknownUpdateOperation(_)
+            | E::IncrementNullField
+            | E::UnableToUpdatePrimaryKey
+            | E::CannotApplyOperation(_)
+            | E::SetIsNotADocument
+            | E::UpsertError(_) => Error::InvalidUpdate { message },
+            E::InvalidOrderOfIndex(_)
+            | E::IndexAlreadyExists(_)
+            | E::IllegalIndexName(_)
+            | E::OnlySupportSingleFieldIndexes(_)
+            | E::OnlySupportsAscendingOrder(_) => Error::InvalidIndex { message },
+            E::InvalidField(_)
+            | E::ValidationError(_)
+            | E::FieldTypeUnexpected(_)
+            | E::UnexpectedTypeForOp(_)
+            | E::ParseError(_)
+            | E::NotAValidKeyType(_)
+            | E::UnexpectedIdType(..)
+            | E::IllegalCollectionName(_)
+            | E::RegexError(_)
+            | E::UnknownAggregationOperation(_)
+            | E::InvalidAggregationStage(_) => Error::InvalidQuery { message },
+            E::StartTransactionInAnotherTransaction
+            | E::RollbackNotInTransaction
+            | E::NoTransactionStarted
+            | E::SessionOutdated
+            | E::CannotWriteDbWithoutTransaction => Error::Transaction { message },
+            E::RocksDbErr(ref detail) => {
+                // RocksDB reports every failure as a string prefixed with its status.
+                if detail.starts_with("Corruption") {
+                    Error::Corrupted { message }
+                } else if detail.contains("lock")
+                    || detail.starts_with("Resource busy")
+                    || detail.starts_with("Operation timed out")
+                    || detail.starts_with("Operation aborted")
+                {
+                    Error::Locked { message }
+                } else if detail.starts_with("IO error") {
+                    Error::Io { message }
+                } else {
+                    Error::Database { message }
+                }
+            }
+            _ => Error::Database { message },
+        }
+    }
+}
+
+/// Converts the argument `argument` (at `path` within it, if given) to a BSON document, failing
+/// with [`Error::Serialization`] when it does not serialize to one.
+fn to_document_at<T: Serialize + ?Sized>(
+    argument: &str,
+    path: Option<String>,
+    value: &T,
+) -> Result<Document, Error> {
+    match to_bson(value) {
+        Ok(Bson::Document(document)) => Ok(document),
+        Ok(other) => Err(Error::argument(
+            argument,
+            path,
+            format!("expected a document, found {:?}", other.element_type()),
+        )),
+        Err(e) => Err(Error::argument(argument, path, e)),
+    }
+}
+
+/// Converts the argument `argument` to a BSON document without panicking on values that are not
+/// documents.
+pub(crate) fn to_argument<T: Serialize + ?Sized>(
+    argument: &str,
+    value: &T,
+) -> Result<Document, Error> {
+    to_document_at(argument, None, value)
+}
+
+/// Converts every value of the list argument `argument` to a BSON document, reporting the index of
+/// the first one that fails.
+pub(crate) fn to_arguments<T: Serialize>(
+    argument: &str,
+    values: &[T],
+) -> Result<Vec<Document>, Error> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| to_document_at(argument, Some(i.to_string()), value))
+        .collect()
+}
+
+/// Reads a daemon response as `T`. The error names what did not fit, such as a field a projection
+/// left out that `T` requires.
+pub(crate) fn from_response<T: DeserializeOwned>(value: Value) -> Result<T, Error> {
+    serde_json::from_value(value).map_err(|e| {
+        Error::serialization(format!(
+            "Failed to read the response as {}: {}",
+            std::any::type_name::<T>(),
+            e
+        ))
+    })
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<polodb_core::bson::ser::Error> for Error {
+    fn from(error: polodb_core::bson::ser::Error) -> Self {
+        Error::serialization(error)
+    }
+}
+
+impl From<polodb_core::bson::de::Error> for Error {
+    fn from(error: polodb_core::bson::de::Error) -> Self {
+        Error::serialization(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polodb_core::bson::{doc, Document};
+    use serde::Deserialize;
+    use serde_json::Value;
+
+    use super::{from_response, Error};
+    use crate::projection::Projection;
+
+    #[derive(Deserialize, Debug)]
+    struct User {
+        name: String,
+        #[allow(dead_code)]
+        age: i32,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Name {
+        name: String,
+    }
+
+    /// What a find with `projection` sends back, as the daemon serializes it.
+    fn projected(projection: Document) -> Value {
+        let document = doc! { "_id": 1, "name": "Ada", "age": 36 };
+        let projected = Projection::parse(&projection).unwrap().apply(document);
+        serde_json::to_value(vec![projected]).unwrap()
+    }
+
+    #[test]
+    fn projection_dropping_a_required_field_names_it() {
+        match from_response::<Vec<User>>(projected(doc! { "name": 1 })) {
+            Err(Error::Serialization { message, .. }) => {
+                assert!(message.contains("missing field `age`"), "{}", message);
+                assert!(message.contains("User"), "{}", message);
+            }
+            other => panic!("expected a serialization error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn projection_reads_through_a_matching_type() {
+        let names = from_response::<Vec<Name>>(projected(doc! { "name": 1 })).unwrap();
+        assert_eq!(names[0].name, "Ada");
+        let users = from_response::<Vec<User>>(projected(doc! { "_id": 0 })).unwrap();
+        assert_eq!(users[0].name, "Ada");
+    }
+}
@@ -0,0 +1,454 @@
+use std::{future::Future, marker::PhantomData};
+
+use polodb_core::bson::{doc, Bson, Document};
+use serde::{de::DeserializeOwned, Serialize};
+use tauri::Runtime;
+use uuid::Uuid;
+
+use crate::{
+    changes::{ChangeFilter, ChangeRecord},
+    daemon::{FindAndModifyOptions, FindOptions, IndexDefinition, RequestOptions},
+    desktop::Polodb,
+    live::{LiveQuery, LiveQueryOptions, LiveQueryUpdate},
+};
+
+/// An open database, as returned by [`Polodb::database`]. Mirrors the guest-js `Database` class.
+pub struct Database<R: Runtime> {
+    polodb: Polodb<R>,
+    key: String,
+}
+
+impl<R: Runtime> Clone for Database<R> {
+    fn clone(&self) -> Self {
+        Database {
+            polodb: self.polodb.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<R: Runtime> Database<R> {
+    pub(crate) fn new(polodb: Polodb<R>, key: String) -> Self {
+        Database { polodb, key }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The transaction this handle's operations run in, if any.
+    pub fn transaction_id(&self) -> Option<Uuid> {
+        self.polodb.transaction
+    }
+
+    /// Returns a copy of this handle whose calls are sent with `request`.
+    pub fn with_request(&self, request: RequestOptions) -> Database<R> {
+        Database::new(self.polodb.with_request(request), self.key.clone())
+    }
+
+    /// Returns a handle to the collection `name`, whose documents are read and written as `T`.
+    pub fn collection<T: Serialize + DeserializeOwned, N: AsRef<str>>(
+        &self,
+        name: N,
+    ) -> Collection<R, T> {
+        Collection {
+            polodb: self.polodb.clone(),
+            database: self.key.clone(),
+            name: name.as_ref().to_string(),
+            document: PhantomData,
+        }
+    }
+
+    pub async fn collections(&self) -> Result<Vec<String>, crate::Error> {
+        self.polodb.list_collections(&self.key).await
+    }
+
+    pub async fn save_as<P: AsRef<str>>(&self, path: P) -> Result<String, crate::Error> {
+        self.polodb.save_as(&self.key, path).await
+    }
+
+    pub async fn close(&self) -> Result<String, crate::Error> {
+        self.polodb.close_database(&self.key).await
+    }
+
+    /// Calls `callback` with every committed change to this database.
+    pub fn subscribe<F: Fn(&ChangeRecord) + Send + Sync + 'static>(
+        &self,
+        callback: F,
+    ) -> Result<Uuid, crate::Error> {
+        self.polodb.subscribe(
+            ChangeFilter {
+                database: Some(self.key.clone()),
+                ..Default::default()
+            },
+            callback,
+        )
+    }
+
+    /// Runs `operation` inside a new transaction, as [`Polodb::transaction`] does. Collections
+    /// taken from the handle passed to `operation` run inside the transaction.
+    pub async fn transaction<T, F, Fut>(&self, operation: F) -> Result<T, crate::Error>
+    where
+        F: FnOnce(Database<R>) -> Fut,
+        Fut: Future<Output = Result<T, crate::Error>>,
+    {
+        let key = self.key.clone();
+        self.polodb
+            .transaction(&self.key, move |polodb| {
+                operation(Database::new(polodb, key))
+            })
+            .await
+    }
+}
+
+/// A collection whose documents are read and written as `T`, as returned by
+/// [`Database::collection`]. Mirrors the guest-js `Collection` class.
+pub struct Collection<R: Runtime, T = Document> {
+    polodb: Polodb<R>,
+    database: String,
+    name: String,
+    document: PhantomData<fn() -> T>,
+}
+
+impl<R: Runtime, T> Clone for Collection<R, T> {
+    fn clone(&self) -> Self {
+        Collection {
+            polodb: self.polodb.clone(),
+            database: self.database.clone(),
+            name: self.name.clone(),
+            document: PhantomData,
+        }
+    }
+}
+
+impl<R: Runtime, T: Serialize + DeserializeOwned> Collection<R, T> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn database(&self) -> Database<R> {
+        Database::new(self.polodb.clone(), self.database.clone())
+    }
+
+    /// Returns a handle to the same collection whose documents are read and written as `U`, for
+    /// reading projections that leave out fields `T` requires.
+    pub fn as_type<U: Serialize + DeserializeOwned>(&self) -> Collection<R, U> {
+        Collection {
+            polodb: self.polodb.clone(),
+            database: self.database.clone(),
+            name: self.name.clone(),
+            document: PhantomData,
+        }
+    }
+
+    pub async fn insert(&self, documents: Vec<T>) -> Result<Vec<Bson>, crate::Error> {
+        self.polodb
+            .insert(&self.database, &self.name, documents)
+            .await
+    }
+
+    pub async fn insert_one(&self, document: T) -> Result<Bson, crate::Error> {
+        self.polodb
+            .insert_one(&self.database, &self.name, document)
+            .await
+    }
+
+    pub async fn find<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+    ) -> Result<Vec<T>, crate::Error> {
+        self.polodb.find(&self.database, &self.name, query).await
+    }
+
+    pub async fn find_with_options<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+        options: FindOptions,
+    ) -> Result<Vec<T>, crate::Error> {
+        self.polodb
+            .find_with_options(&self.database, &self.name, query, options)
+            .await
+    }
+
+    pub async fn find_one<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+    ) -> Result<Option<T>, crate::Error> {
+        self.polodb
+            .find_one(&self.database, &self.name, query)
+            .await
+    }
+
+    pub async fn find_one_with_options<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+        options: FindOptions,
+    ) -> Result<Option<T>, crate::Error> {
+        self.polodb
+            .find_one_with_options(&self.database, &self.name, query, options)
+            .await
+    }
+
+    /// Looks up the document whose `_id` is `id`.
+    pub async fn get<I: Into<Bson>>(&self, id: I) -> Result<Option<T>, crate::Error> {
+        self.find_one(doc! { "_id": id.into() }).await
+    }
+
+    pub async fn all(&self) -> Result<Vec<T>, crate::Error> {
+        self.polodb.all(&self.database, &self.name).await
+    }
+
+    pub async fn all_with_options(&self, options: FindOptions) -> Result<Vec<T>, crate::Error> {
+        self.polodb
+            .all_with_options(&self.database, &self.name, options)
+            .await
+    }
+
+    /// Counts the documents matching `query`.
+    pub async fn count<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+    ) -> Result<u64, crate::Error> {
+        self.polodb.count(&self.database, &self.name, query).await
+    }
+
+    pub async fn estimated_count(&self) -> Result<u64, crate::Error> {
+        self.polodb
+            .estimated_count(&self.database, &self.name)
+            .await
+    }
+
+    /// The distinct values of the dotted `field` across the documents matching `query`.
+    pub async fn distinct<Query: Serialize + DeserializeOwned, F: AsRef<str>>(
+        &self,
+        field: F,
+        query: Query,
+    ) -> Result<Vec<Bson>, crate::Error> {
+        self.polodb
+            .distinct(&self.database, &self.name, field, query)
+            .await
+    }
+
+    /// Opens a cursor over the documents matching `query`, to read them in batches.
+    pub async fn cursor<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+        options: FindOptions,
+    ) -> Result<Cursor<R, T>, crate::Error> {
+        let id = self
+            .polodb
+            .open_cursor(&self.database, &self.name, query, options)
+            .await?;
+        Ok(Cursor {
+            polodb: self.polodb.clone(),
+            id,
+            exhausted: false,
+            document: PhantomData,
+        })
+    }
+
+    pub async fn aggregate<
+        Output: Serialize + DeserializeOwned,
+        Stage: Serialize + DeserializeOwned,
+    >(
+        &self,
+        pipeline: Vec<Stage>,
+    ) -> Result<Vec<Output>, crate::Error> {
+        self.polodb
+            .aggregate(&self.database, &self.name, pipeline)
+            .await
+    }
+
+    pub async fn update_many<
+        Query: Serialize + DeserializeOwned,
+        Update: Serialize + DeserializeOwned,
+    >(
+        &self,
+        query: Query,
+        update: Update,
+        upsert: bool,
+    ) -> Result<u64, crate::Error> {
+        self.polodb
+            .update(&self.database, &self.name, query, update, upsert)
+            .await
+    }
+
+    pub async fn update_one<
+        Query: Serialize + DeserializeOwned,
+        Update: Serialize + DeserializeOwned,
+    >(
+        &self,
+        query: Query,
+        update: Update,
+        upsert: bool,
+    ) -> Result<u64, crate::Error> {
+        self.polodb
+            .update_one(&self.database, &self.name, query, update, upsert)
+            .await
+    }
+
+    pub async fn update_all<Update: Serialize + DeserializeOwned>(
+        &self,
+        update: Update,
+        upsert: bool,
+    ) -> Result<u64, crate::Error> {
+        self.polodb
+            .update_all(&self.database, &self.name, update, upsert)
+            .await
+    }
+
+    /// Replaces the first document matching `query` with `replacement`, keeping its `_id`.
+    pub async fn replace_one<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+        replacement: T,
+        upsert: bool,
+    ) -> Result<u64, crate::Error> {
+        self.polodb
+            .replace_one(&self.database, &self.name, query, replacement, upsert)
+            .await
+    }
+
+    pub async fn find_one_and_update<
+        Query: Serialize + DeserializeOwned,
+        Update: Serialize + DeserializeOwned,
+    >(
+        &self,
+        query: Query,
+        update: Update,
+        options: FindAndModifyOptions,
+    ) -> Result<Option<T>, crate::Error> {
+        self.polodb
+            .find_one_and_update(&self.database, &self.name, query, update, options)
+            .await
+    }
+
+    pub async fn find_one_and_replace<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+        replacement: T,
+        options: FindAndModifyOptions,
+    ) -> Result<Option<T>, crate::Error> {
+        self.polodb
+            .find_one_and_replace(&self.database, &self.name, query, replacement, options)
+            .await
+    }
+
+    pub async fn find_one_and_delete<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+        options: FindAndModifyOptions,
+    ) -> Result<Option<T>, crate::Error> {
+        self.polodb
+            .find_one_and_delete(&self.database, &self.name, query, options)
+            .await
+    }
+
+    pub async fn delete_many<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+    ) -> Result<u64, crate::Error> {
+        self.polodb.delete(&self.database, &self.name, query).await
+    }
+
+    pub async fn delete_one<Query: Serialize + DeserializeOwned>(
+        &self,
+        query: Query,
+    ) -> Result<u64, crate::Error> {
+        self.polodb
+            .delete_one(&self.database, &self.name, query)
+            .await
+    }
+
+    pub async fn delete_all(&self) -> Result<u64, crate::Error> {
+        self.polodb.delete_all(&self.database, &self.name).await
+    }
+
+    pub async fn create_index(&self, index: IndexDefinition) -> Result<String, crate::Error> {
+        self.polodb
+            .create_index(&self.database, &self.name, index)
+            .await
+    }
+
+    pub async fn drop_index<N: AsRef<str>>(&self, name: N) -> Result<(), crate::Error> {
+        self.polodb
+            .drop_index(&self.database, &self.name, name)
+            .await
+    }
+
+    pub async fn indexes(&self) -> Result<Vec<IndexDefinition>, crate::Error> {
+        self.polodb.list_indexes(&self.database, &self.name).await
+    }
+
+    pub async fn drop(&self) -> Result<(), crate::Error> {
+        self.polodb
+            .drop_collection(&self.database, &self.name)
+            .await
+    }
+
+    /// Calls `callback` with every committed change to this collection.
+    pub fn subscribe<F: Fn(&ChangeRecord) + Send + Sync + 'static>(
+        &self,
+        callback: F,
+    ) -> Result<Uuid, crate::Error> {
+        self.polodb.subscribe(
+            ChangeFilter {
+                database: Some(self.database.clone()),
+                collection: Some(self.name.clone()),
+                ..Default::default()
+            },
+            callback,
+        )
+    }
+
+    /// Runs `query` and calls `on_update` whenever its results change, as
+    /// [`Polodb::watch_query`] does.
+    pub async fn watch<
+        Query: Serialize + DeserializeOwned,
+        F: Fn(LiveQueryUpdate) -> bool + Send + 'static,
+    >(
+        &self,
+        query: Query,
+        options: LiveQueryOptions,
+        on_update: F,
+    ) -> Result<LiveQuery, crate::Error> {
+        self.polodb
+            .watch_query(&self.database, &self.name, query, options, None, on_update)
+            .await
+    }
+}
+
+/// A cursor over a query's results, as returned by [`Collection::cursor`].
+pub struct Cursor<R: Runtime, T = Document> {
+    polodb: Polodb<R>,
+    id: Uuid,
+    exhausted: bool,
+    document: PhantomData<fn() -> T>,
+}
+
+impl<R: Runtime, T: Serialize + DeserializeOwned> Cursor<R, T> {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// Reads up to `size` more documents. Returns an empty batch once the cursor is exhausted.
+    pub async fn next(&mut self, size: usize) -> Result<Vec<T>, crate::Error> {
+        if self.exhausted {
+            return Ok(Vec::new());
+        }
+        let batch = self.polodb.next_batch::<T>(self.id, size).await?;
+        self.exhausted = batch.exhausted;
+        Ok(batch.documents)
+    }
+
+    pub async fn close(self) -> Result<(), crate::Error> {
+        if self.exhausted {
+            return Ok(());
+        }
+        self.polodb.close_cursor(self.id).await
+    }
+}
@@ -1,20 +1,43 @@
 use tauri::{
-    plugin::{Builder, TauriPlugin},
-    Manager, Runtime,
+    plugin::{Builder as PluginBuilder, TauriPlugin},
+    utils::config::FsScope,
+    AppHandle, Manager, RunEvent, Runtime, WindowEvent,
 };
 
 #[cfg(desktop)]
 mod desktop;
 
+mod changes;
 mod commands;
+mod config;
 mod daemon;
 mod error;
+#[cfg(desktop)]
+mod handle;
+mod live;
+mod projection;
+mod scope;
+mod timer;
 
+pub use changes::{ChangeFilter, ChangeOperation, ChangeRecord};
 use commands::{
-    close_database, delete, delete_all, delete_one, find, find_all, find_one, insert, insert_one,
-    list_databases, open_database, update, update_all, update_one, list_collections
+    abort_transaction, aggregate, begin_transaction, cancel, close_cursor, close_database,
+    commit_transaction, count, create_index, daemon_status, delete, delete_all, delete_one,
+    distinct, drop_collection, drop_index, estimated_count, find, find_all, find_one,
+    find_one_and_delete, find_one_and_replace, find_one_and_update, forget_database, insert,
+    insert_one, list_collections, list_databases, list_indexes, list_known_databases, next_batch,
+    open_cursor, open_database, open_memory_database, replace_one, save_as, subscribe, unsubscribe,
+    unwatch, update, update_all, update_one, watch_query,
+};
+pub use config::{Backpressure, Config, DaemonOptions, DatabaseDeclaration, IndexDeclaration};
+pub use daemon::{
+    CursorBatch, DaemonStatus, DatabaseStatus, FailedDatabase, FindAndModifyOptions, FindOptions,
+    IndexDefinition, Priority, PriorityCounts, RequestOptions, ReturnDocument, SerializedDatabase,
 };
 pub use error::Error;
+#[cfg(desktop)]
+pub use handle::{Collection, Cursor, Database};
+pub use live::{LiveQuery, LiveQueryMode, LiveQueryOptions, LiveQueryUpdate};
 
 #[cfg(desktop)]
 use desktop::Polodb;
@@ -30,31 +53,172 @@ impl<R: Runtime, T: Manager<R>> crate::PolodbExt<R> for T {
     }
 }
 
-/// Initializes the plugin.
+/// Closes every database before the app goes away. Shutting down twice is harmless.
+fn shutdown<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(polodb) = app.try_state::<Polodb<R>>() {
+        if let Err(e) = polodb.shutdown() {
+            log::error!("shutdown failed: {}", e);
+        }
+    }
+}
+
+/// Initializes the plugin with the configuration from `tauri.conf.json`.
 pub fn init<R: Runtime>() -> TauriPlugin<R> {
-    Builder::new("polodb")
-        .invoke_handler(tauri::generate_handler![
-            list_databases,
-            open_database,
-            close_database,
-            insert,
-            insert_one,
-            find,
-            find_all,
-            find_one,
-            delete,
-            delete_all,
-            delete_one,
-            update,
-            update_all,
-            update_one,
-            list_collections
-        ])
-        .setup(|app, api| {
-            #[cfg(desktop)]
-            let polodb = desktop::init(app, api).unwrap();
-            app.manage(polodb);
-            Ok(())
-        })
-        .build()
+    Builder::new().build()
+}
+
+/// Builds the plugin, layering settings on top of the `plugins.polodb` section of `tauri.conf.json`.
+/// Databases and indexes are added to the configured ones, everything else replaces them.
+#[derive(Default)]
+pub struct Builder {
+    directory: Option<String>,
+    databases: Vec<DatabaseDeclaration>,
+    indexes: Vec<IndexDeclaration>,
+    scope: Option<FsScope>,
+    daemon: Option<DaemonOptions>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Sets the directory relative database paths are resolved against.
+    pub fn directory<P: AsRef<str>>(mut self, directory: P) -> Self {
+        self.directory = Some(directory.as_ref().to_string());
+        self
+    }
+
+    /// Opens a database under `key` when the plugin starts.
+    pub fn database<K: AsRef<str>, P: AsRef<str>>(mut self, key: K, path: P) -> Self {
+        self.databases.push(DatabaseDeclaration {
+            key: key.as_ref().to_string(),
+            path: path.as_ref().to_string(),
+            read_only: false,
+        });
+        self
+    }
+
+    /// Opens a database under `key` when the plugin starts, rejecting every write to it.
+    pub fn read_only_database<K: AsRef<str>, P: AsRef<str>>(mut self, key: K, path: P) -> Self {
+        self.databases.push(DatabaseDeclaration {
+            key: key.as_ref().to_string(),
+            path: path.as_ref().to_string(),
+            read_only: true,
+        });
+        self
+    }
+
+    pub fn index(mut self, index: IndexDeclaration) -> Self {
+        self.indexes.push(index);
+        self
+    }
+
+    /// Sets the paths the frontend may open databases at, on top of those granted by permission scopes.
+    pub fn scope(mut self, scope: FsScope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    pub fn daemon(mut self, options: DaemonOptions) -> Self {
+        self.daemon = Some(options);
+        self
+    }
+
+    fn configure(self, mut config: Config) -> Config {
+        if self.directory.is_some() {
+            config.directory = self.directory;
+        }
+        config.databases.extend(self.databases);
+        config.indexes.extend(self.indexes);
+        if let Some(scope) = self.scope {
+            config.scope = scope;
+        }
+        if let Some(daemon) = self.daemon {
+            config.daemon = daemon;
+        }
+        config
+    }
+
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        PluginBuilder::<R, Option<Config>>::new("polodb")
+            .invoke_handler(tauri::generate_handler![
+                list_databases,
+                open_database,
+                close_database,
+                insert,
+                insert_one,
+                find,
+                find_all,
+                find_one,
+                delete,
+                delete_all,
+                delete_one,
+                update,
+                update_all,
+                update_one,
+                replace_one,
+                find_one_and_update,
+                find_one_and_replace,
+                find_one_and_delete,
+                list_collections,
+                begin_transaction,
+                commit_transaction,
+                abort_transaction,
+                count,
+                estimated_count,
+                distinct,
+                aggregate,
+                create_index,
+                drop_index,
+                list_indexes,
+                open_cursor,
+                next_batch,
+                close_cursor,
+                drop_collection,
+                subscribe,
+                unsubscribe,
+                watch_query,
+                unwatch,
+                list_known_databases,
+                forget_database,
+                open_memory_database,
+                save_as,
+                daemon_status,
+                cancel
+            ])
+            .setup(move |app, api| {
+                let config = self.configure(api.config().clone().unwrap_or_default());
+                #[cfg(desktop)]
+                let polodb = desktop::init(app, config)?;
+                app.manage(polodb);
+                Ok(())
+            })
+            .on_event(|app, event| match event {
+                RunEvent::WindowEvent {
+                    label,
+                    event: WindowEvent::Destroyed,
+                    ..
+                } => {
+                    let app = app.clone();
+                    let label = label.clone();
+                    app.polodb().cancel_requests(&label);
+                    app.polodb().release_subscriptions(&label);
+                    tauri::async_runtime::spawn(async move {
+                        let _ = app.polodb().release_transactions(label).await;
+                    });
+                }
+                RunEvent::Exit => shutdown(app),
+                _ => (),
+            })
+            .on_navigation(|webview, _| {
+                // Nobody is left to receive the responses to the previous page's requests.
+                if let Some(polodb) = webview.try_state::<Polodb<R>>() {
+                    polodb.cancel_requests(webview.label());
+                }
+                true
+            })
+            .on_drop(|app| shutdown(&app))
+            .build()
+    }
 }
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::daemon::FindOptions;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveQueryMode {
+    /// Push the whole result set every time it changes.
+    #[default]
+    Results,
+    /// Push only the documents that were added, removed or changed, keyed by `_id`.
+    Diff,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveQueryOptions {
+    #[serde(flatten)]
+    pub find: FindOptions,
+    #[serde(default)]
+    pub mode: LiveQueryMode,
+}
+
+/// A registered live query along with its initial result set.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct LiveQuery {
+    pub id: Uuid,
+    pub documents: Vec<Value>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveQueryUpdate {
+    Results {
+        documents: Vec<Value>,
+    },
+    Diff {
+        added: Vec<Value>,
+        removed: Vec<Value>,
+        changed: Vec<Value>,
+    },
+    /// Re-running the query after a write failed. The query stays registered and is re-run on the
+    /// next write.
+    Error {
+        error: crate::Error,
+    },
+}
+
+impl LiveQueryUpdate {
+    /// Describes how a result set changed, or returns `None` if it did not. Falls back to a full
+    /// result set when a document has no `_id` to key the diff on.
+    pub fn between(previous: &[Value], current: &[Value], mode: LiveQueryMode) -> Option<Self> {
+        if previous == current {
+            return None;
+        }
+        let keyed = previous
+            .iter()
+            .chain(current)
+            .all(|d| d.get("_id").is_some());
+        if mode == LiveQueryMode::Results || !keyed {
+            return Some(LiveQueryUpdate::Results {
+                documents: current.to_vec(),
+            });
+        }
+
+        let key = |d: &Value| d.get("_id").map(|id| id.to_string()).unwrap_or_default();
+        let before = previous
+            .iter()
+            .map(|d| (key(d), d))
+            .collect::<HashMap<String, &Value>>();
+        let after = current
+            .iter()
+            .map(|d| (key(d), d))
+            .collect::<HashMap<String, &Value>>();
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for document in current {
+            match before.get(&key(document)) {
+                None => added.push(document.clone()),
+                Some(old) if *old != document => changed.push(document.clone()),
+                _ => (),
+            }
+        }
+        let removed = previous
+            .iter()
+            .filter(|d| !after.contains_key(&key(d)))
+            .filter_map(|d| d.get("_id").cloned())
+            .collect::<Vec<Value>>();
+
+        if added.is_empty() && removed.is_empty() && changed.is_empty() {
+            // Only the order moved; there is nothing to diff.
+            return Some(LiveQueryUpdate::Results {
+                documents: current.to_vec(),
+            });
+        }
+        Some(LiveQueryUpdate::Diff {
+            added,
+            removed,
+            changed,
+        })
+    }
+}
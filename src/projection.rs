@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use polodb_core::bson::{Bson, Document};
+
+#[derive(Clone, Debug)]
+enum Node {
+    Leaf,
+    Branch(BTreeMap<String, Node>),
+}
+
+/// A MongoDB-style field projection, applied to documents before they leave the daemon.
+///
+/// Fields are either all included (`1`/`true`) or all excluded (`0`/`false`), except for `_id`, which is
+/// always returned unless explicitly excluded. Dotted paths select fields of embedded documents, and of
+/// every document inside embedded arrays.
+#[derive(Clone, Debug)]
+pub struct Projection {
+    include: bool,
+    keep_id: bool,
+    fields: BTreeMap<String, Node>,
+}
+
+impl Projection {
+    pub fn parse(projection: &Document) -> Result<Self, crate::Error> {
+        let mut include: Option<bool> = None;
+        let mut keep_id = true;
+        let mut explicit_id = false;
+        let mut fields: BTreeMap<String, Node> = BTreeMap::new();
+
+        for (path, value) in projection {
+            let selected = match value {
+                Bson::Boolean(b) => *b,
+                Bson::Int32(i) => *i != 0,
+                Bson::Int64(i) => *i != 0,
+                Bson::Double(f) => *f != 0.0,
+                other => {
+                    return Err(crate::Error::invalid_query(format!(
+                        "Invalid projection value for {:?}: {}",
+                        path, other
+                    )))
+                }
+            };
+
+            if path == "_id" {
+                keep_id = selected;
+                explicit_id = selected;
+                continue;
+            }
+
+            match include {
+                Some(mode) if mode != selected => {
+                    return Err(crate::Error::invalid_query(format!(
+                        "Cannot mix inclusion and exclusion in projection (at {:?})",
+                        path
+                    )))
+                }
+                _ => include = Some(selected),
+            }
+
+            let mut level = &mut fields;
+            let mut parts = path.split('.').peekable();
+            while let Some(part) = parts.next() {
+                if parts.peek().is_none() {
+                    level.insert(part.to_string(), Node::Leaf);
+                    break;
+                }
+                let node = level
+                    .entry(part.to_string())
+                    .or_insert_with(|| Node::Branch(BTreeMap::new()));
+                match node {
+                    Node::Branch(children) => level = children,
+                    Node::Leaf => break,
+                }
+            }
+        }
+
+        Ok(Projection {
+            // `{_id: 1}` on its own selects only the `_id`.
+            include: include.unwrap_or(explicit_id),
+            keep_id,
+            fields,
+        })
+    }
+
+    pub fn apply(&self, document: Document) -> Document {
+        let id = document.get("_id").cloned();
+        let mut projected = if self.include {
+            Projection::include(document, &self.fields)
+        } else {
+            Projection::exclude(document, &self.fields)
+        };
+
+        match (self.keep_id, id) {
+            (true, Some(id)) if !projected.contains_key("_id") => {
+                let mut with_id = Document::new();
+                with_id.insert("_id", id);
+                with_id.extend(projected);
+                with_id
+            }
+            (false, _) => {
+                projected.remove("_id");
+                projected
+            }
+            _ => projected,
+        }
+    }
+
+    fn include(document: Document, fields: &BTreeMap<String, Node>) -> Document {
+        let mut result = Document::new();
+        for (key, value) in document {
+            match (fields.get(&key), value) {
+                (Some(Node::Leaf), value) => {
+                    result.insert(key, value);
+                }
+                (Some(Node::Branch(children)), Bson::Document(inner)) => {
+                    result.insert(key, Projection::include(inner, children));
+                }
+                (Some(Node::Branch(children)), Bson::Array(items)) => {
+                    result.insert(
+                        key,
+                        items
+                            .into_iter()
+                            .filter_map(|item| match item {
+                                Bson::Document(inner) => {
+                                    Some(Bson::Document(Projection::include(inner, children)))
+                                }
+                                _ => None,
+                            })
+                            .collect::<Vec<Bson>>(),
+                    );
+                }
+                _ => (),
+            }
+        }
+        result
+    }
+
+    fn exclude(document: Document, fields: &BTreeMap<String, Node>) -> Document {
+        let mut result = Document::new();
+        for (key, value) in document {
+            match (fields.get(&key), value) {
+                (Some(Node::Leaf), _) => (),
+                (Some(Node::Branch(children)), Bson::Document(inner)) => {
+                    result.insert(key, Projection::exclude(inner, children));
+                }
+                (Some(Node::Branch(children)), Bson::Array(items)) => {
+                    result.insert(
+                        key,
+                        items
+                            .into_iter()
+                            .map(|item| match item {
+                                Bson::Document(inner) => {
+                                    Bson::Document(Projection::exclude(inner, children))
+                                }
+                                other => other,
+                            })
+                            .collect::<Vec<Bson>>(),
+                    );
+                }
+                (_, value) => {
+                    result.insert(key, value);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polodb_core::bson::{doc, Document};
+
+    use super::Projection;
+
+    fn project(projection: Document, document: Document) -> Document {
+        Projection::parse(&projection).unwrap().apply(document)
+    }
+
+    fn record() -> Document {
+        doc! {
+            "_id": 1,
+            "name": "Ada",
+            "blob": "...",
+            "profile": { "age": 36, "city": "London" },
+            "tags": [{ "label": "a", "weight": 1 }, { "label": "b", "weight": 2 }],
+        }
+    }
+
+    #[test]
+    fn id_only_inclusion_returns_only_the_id() {
+        assert_eq!(project(doc! { "_id": 1 }, record()), doc! { "_id": 1 });
+        assert_eq!(project(doc! { "_id": true }, record()), doc! { "_id": 1 });
+    }
+
+    #[test]
+    fn id_only_exclusion_drops_only_the_id() {
+        let mut expected = record();
+        expected.remove("_id");
+        assert_eq!(project(doc! { "_id": 0 }, record()), expected);
+    }
+
+    #[test]
+    fn empty_projection_returns_the_whole_document() {
+        assert_eq!(project(doc! {}, record()), record());
+    }
+
+    #[test]
+    fn inclusion_keeps_the_id_unless_excluded() {
+        assert_eq!(
+            project(doc! { "name": 1 }, record()),
+            doc! { "_id": 1, "name": "Ada" }
+        );
+        assert_eq!(
+            project(doc! { "name": 1, "_id": 0 }, record()),
+            doc! { "name": "Ada" }
+        );
+    }
+
+    #[test]
+    fn exclusion_can_also_drop_the_id() {
+        assert_eq!(
+            project(
+                doc! { "blob": 0, "tags": 0, "profile": 0, "_id": 0 },
+                record()
+            ),
+            doc! { "name": "Ada" }
+        );
+    }
+
+    #[test]
+    fn nested_paths_reach_into_documents_and_arrays() {
+        assert_eq!(
+            project(doc! { "profile.city": 1, "tags.label": 1 }, record()),
+            doc! {
+                "_id": 1,
+                "profile": { "city": "London" },
+                "tags": [{ "label": "a" }, { "label": "b" }],
+            }
+        );
+        assert_eq!(
+            project(
+                doc! { "profile.age": 0, "tags.weight": 0, "blob": 0 },
+                record()
+            ),
+            doc! {
+                "_id": 1,
+                "name": "Ada",
+                "profile": { "city": "London" },
+                "tags": [{ "label": "a" }, { "label": "b" }],
+            }
+        );
+    }
+
+    #[test]
+    fn mixing_inclusion_and_exclusion_is_rejected() {
+        assert!(Projection::parse(&doc! { "name": 1, "blob": 0 }).is_err());
+        assert!(Projection::parse(&doc! { "name": "yes" }).is_err());
+    }
+}
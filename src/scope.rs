@@ -0,0 +1,340 @@
+use std::path::{Component, Path, PathBuf};
+
+use serde::Deserialize;
+use tauri::{
+    ipc::{CommandArg, CommandItem, CommandScope, GlobalScope, InvokeError},
+    utils::config::FsScope,
+    AppHandle, Manager, Runtime,
+};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    Read,
+    Write,
+    #[default]
+    All,
+}
+
+impl Access {
+    fn name(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::All => "all",
+        }
+    }
+
+    fn covers(self, requested: Access) -> bool {
+        self == Access::All || self == requested
+    }
+}
+
+fn any() -> String {
+    "*".to_string()
+}
+
+/// An entry of the plugin's permission scopes. Path entries (in the same shape as `tauri-plugin-fs`
+/// scope entries) govern where databases may be opened, data entries govern which databases and
+/// collections may be read or written. `*` in a data entry matches any run of characters.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ScopeEntry {
+    Value(PathBuf),
+    Object {
+        path: PathBuf,
+    },
+    Data {
+        database: String,
+        #[serde(default = "any")]
+        collection: String,
+        #[serde(default)]
+        access: Access,
+    },
+}
+
+impl ScopeEntry {
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            ScopeEntry::Value(path) => Some(path),
+            ScopeEntry::Object { path } => Some(path),
+            ScopeEntry::Data { .. } => None,
+        }
+    }
+
+    /// Whether this entry grants `access` to every name in `database`/`collection` (`None` meaning any).
+    fn grants(&self, database: Option<&str>, collection: Option<&str>, access: Access) -> bool {
+        match self {
+            ScopeEntry::Data {
+                database: d,
+                collection: c,
+                access: a,
+            } => {
+                a.covers(access)
+                    && database.map_or(d == "*", |name| wildcard(d, name))
+                    && collection.map_or(c == "*", |name| wildcard(c, name))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether this entry applies to any name in `database`/`collection` (`None` meaning any).
+    fn touches(&self, database: Option<&str>, collection: Option<&str>, access: Access) -> bool {
+        match self {
+            ScopeEntry::Data {
+                database: d,
+                collection: c,
+                access: a,
+            } => {
+                a.covers(access)
+                    && database.map_or(true, |name| wildcard(d, name))
+                    && collection.map_or(true, |name| wildcard(c, name))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Resolves `path`, which may start with a path variable such as `$APPDATA`. Relative paths are
+/// resolved against `directory`, or the working directory if none is configured. `.` and `..`
+/// components are folded away, so they cannot carry the path out of a scope it appears to match.
+pub fn resolve_path<R: Runtime>(
+    app: &AppHandle<R>,
+    directory: Option<&Path>,
+    path: &str,
+) -> Result<PathBuf, crate::Error> {
+    let resolved = app
+        .path()
+        .parse(path)
+        .map_err(|_| crate::Error::io("Invalid path"))?;
+    if resolved.is_absolute() {
+        return Ok(normalize(&resolved));
+    }
+    match directory {
+        Some(directory) => Ok(normalize(&directory.join(resolved))),
+        None => Ok(normalize(
+            &std::env::current_dir()
+                .map_err(|e| crate::Error::io(format!("Failed to resolve {:?}: {}", path, e)))?
+                .join(resolved),
+        )),
+    }
+}
+
+/// Folds `.` and `..` components out of `path` without touching the filesystem, as the database
+/// file may not exist yet. `..` at the root stays at the root.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !matches!(
+                    normalized.components().next_back(),
+                    None | Some(Component::RootDir) | Some(Component::Prefix(_))
+                ) {
+                    normalized.pop();
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Whether the configured scope alone allows `path`, for databases reopened without a calling window.
+pub fn configured_allows<R: Runtime>(
+    app: &AppHandle<R>,
+    configured: &FsScope,
+    path: &Path,
+) -> Result<bool, crate::Error> {
+    let scope = tauri::scope::fs::Scope::new(app, configured)
+        .map_err(|e| crate::Error::io(format!("Invalid database scope: {}", e)))?;
+    Ok(scope.is_allowed(path))
+}
+
+fn wildcard(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            name.starts_with(prefix) && {
+                let tail = &name[prefix.len()..];
+                tail.char_indices()
+                    .map(|(i, _)| i)
+                    .chain([tail.len()])
+                    .any(|i| wildcard(rest, &tail[i..]))
+            }
+        }
+    }
+}
+
+/// The window that created a transaction, cursor, subscription or pending request, and the
+/// database and collection it reaches (`None` meaning any). Handles made from Rust have no owner.
+#[derive(Clone, Debug, Default)]
+pub struct Handle {
+    pub owner: Option<String>,
+    pub database: Option<String>,
+    pub collection: Option<String>,
+}
+
+impl Handle {
+    /// Fails with [`crate::Error::PermissionDenied`] unless `window` created this handle and may
+    /// still read what it reaches.
+    pub fn check(&self, window: &str, scopes: &Scopes) -> Result<(), crate::Error> {
+        if self.owner.as_deref() != Some(window) {
+            return Err(crate::Error::PermissionDenied {
+                database: self.database.as_deref().unwrap_or("*").to_string(),
+                collection: self.collection.as_deref().unwrap_or("*").to_string(),
+                access: Access::Read.name().to_string(),
+            });
+        }
+        scopes.check(
+            self.database.as_deref(),
+            self.collection.as_deref(),
+            Access::Read,
+        )
+    }
+}
+
+/// The command and global scopes granted to the calling window.
+pub struct Scopes {
+    pub command: CommandScope<ScopeEntry>,
+    pub global: GlobalScope<ScopeEntry>,
+}
+
+impl<'a, R: Runtime> CommandArg<'a, R> for Scopes {
+    fn from_command(command: CommandItem<'a, R>) -> Result<Self, InvokeError> {
+        let global = GlobalScope::from_command(CommandItem {
+            plugin: command.plugin,
+            name: command.name,
+            key: command.key,
+            message: command.message,
+            acl: command.acl,
+        })?;
+        Ok(Scopes {
+            command: CommandScope::from_command(command)?,
+            global,
+        })
+    }
+}
+
+impl Scopes {
+    fn allows(&self) -> impl Iterator<Item = &ScopeEntry> {
+        self.command
+            .allows()
+            .iter()
+            .chain(self.global.allows())
+            .map(|e| e.as_ref())
+    }
+
+    fn denies(&self) -> impl Iterator<Item = &ScopeEntry> {
+        self.command
+            .denies()
+            .iter()
+            .chain(self.global.denies())
+            .map(|e| e.as_ref())
+    }
+
+    pub fn permits(
+        &self,
+        database: Option<&str>,
+        collection: Option<&str>,
+        access: Access,
+    ) -> bool {
+        self.allows()
+            .any(|e| e.grants(database, collection, access))
+            && !self
+                .denies()
+                .any(|e| e.touches(database, collection, access))
+    }
+
+    /// Whether any access to `database` (or to `collection` inside it) is granted, for filtering listings.
+    pub fn reveals(&self, database: &str, collection: Option<&str>) -> bool {
+        [Access::Read, Access::Write].into_iter().any(|access| {
+            self.allows()
+                .any(|e| e.touches(Some(database), collection, access))
+                && !self
+                    .denies()
+                    .any(|e| e.grants(Some(database), collection, access))
+        })
+    }
+
+    /// Fails with [`crate::Error::PermissionDenied`] unless `access` to `database`/`collection` is granted.
+    pub fn check(
+        &self,
+        database: Option<&str>,
+        collection: Option<&str>,
+        access: Access,
+    ) -> Result<(), crate::Error> {
+        if self.permits(database, collection, access) {
+            Ok(())
+        } else {
+            Err(crate::Error::PermissionDenied {
+                database: database.unwrap_or("*").to_string(),
+                collection: collection.unwrap_or("*").to_string(),
+                access: access.name().to_string(),
+            })
+        }
+    }
+
+    /// Resolves a path sent by the frontend and checks it against the configured scope and the path
+    /// entries granted to the calling window. Relative paths are resolved as by [`resolve_path`].
+    pub fn resolve_database_path<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        configured: &FsScope,
+        directory: Option<&Path>,
+        path: &str,
+    ) -> Result<PathBuf, crate::Error> {
+        let mut allow = configured.allowed_paths().clone();
+        allow.extend(self.allows().filter_map(|e| e.path().cloned()));
+        let mut deny = configured.forbidden_paths().cloned().unwrap_or_default();
+        deny.extend(self.denies().filter_map(|e| e.path().cloned()));
+        let require_literal_leading_dot = match configured {
+            FsScope::Scope {
+                require_literal_leading_dot,
+                ..
+            } => *require_literal_leading_dot,
+            FsScope::AllowedPaths(_) => None,
+        };
+        let scope = tauri::scope::fs::Scope::new(
+            app,
+            &FsScope::Scope {
+                allow,
+                deny,
+                require_literal_leading_dot,
+            },
+        )
+        .map_err(|e| crate::Error::io(format!("Invalid database scope: {}", e)))?;
+
+        let resolved = resolve_path(app, directory, path)?;
+
+        if scope.is_allowed(&resolved) {
+            Ok(resolved)
+        } else {
+            Err(crate::Error::PathForbidden {
+                path: resolved.to_string_lossy().to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::normalize;
+
+    #[test]
+    fn normalize_folds_parent_components() {
+        // `$APPDATA/db/../../x` must not match a `$APPDATA/db/**` scope.
+        assert_eq!(
+            normalize(Path::new("/home/ada/.local/share/app/db/../../x")),
+            PathBuf::from("/home/ada/.local/share/x")
+        );
+        assert_eq!(
+            normalize(Path::new("/data/./db/../main.db")),
+            PathBuf::from("/data/main.db")
+        );
+        assert_eq!(normalize(Path::new("/../../etc")), PathBuf::from("/etc"));
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    future::Future,
+    pin::Pin,
+    sync::{Condvar, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
+
+/// Deadlines waiting to fire. Entries whose waker was removed were dropped and are skipped.
+#[derive(Default)]
+struct Timers {
+    queue: BinaryHeap<Reverse<(Instant, u64)>>,
+    wakers: HashMap<u64, Waker>,
+    next: u64,
+}
+
+/// A single thread wakes every [`Deadline`], so they work under any executor, not only Tokio's.
+struct Timer {
+    timers: Mutex<Timers>,
+    changed: Condvar,
+}
+
+impl Timer {
+    /// The timer, or `None` if its thread could not be started.
+    fn get() -> Option<&'static Timer> {
+        static TIMER: OnceLock<Option<Timer>> = OnceLock::new();
+        TIMER
+            .get_or_init(|| {
+                // The thread waits for the initialization to finish before it first takes the lock.
+                std::thread::Builder::new()
+                    .name("polodb-timer".to_string())
+                    .spawn(|| {
+                        if let Some(timer) = Timer::get() {
+                            timer.run()
+                        }
+                    })
+                    .ok()?;
+                Some(Timer {
+                    timers: Mutex::new(Timers::default()),
+                    changed: Condvar::new(),
+                })
+            })
+            .as_ref()
+    }
+
+    fn run(&self) {
+        let mut timers = self.timers.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let now = Instant::now();
+            while let Some(Reverse((at, id))) = timers.queue.peek().copied() {
+                if at > now {
+                    break;
+                }
+                timers.queue.pop();
+                if let Some(waker) = timers.wakers.remove(&id) {
+                    waker.wake();
+                }
+            }
+            timers = match timers.queue.peek() {
+                Some(Reverse((at, _))) => {
+                    let wait = at.saturating_duration_since(now);
+                    self.changed
+                        .wait_timeout(timers, wait)
+                        .map(|(timers, _)| timers)
+                        .unwrap_or_else(|e| e.into_inner().0)
+                }
+                None => self.changed.wait(timers).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+}
+
+/// Completes once `at` has passed, or fails if the timer thread could not be started.
+pub struct Deadline {
+    at: Instant,
+    id: Option<u64>,
+}
+
+impl Deadline {
+    pub fn at(at: Instant) -> Self {
+        Deadline { at, id: None }
+    }
+}
+
+impl Future for Deadline {
+    type Output = Result<(), crate::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if Instant::now() >= self.at {
+            return Poll::Ready(Ok(()));
+        }
+        let timer = match Timer::get() {
+            Some(timer) => timer,
+            None => {
+                return Poll::Ready(Err(crate::Error::daemon(
+                    "Failed to start the timer thread",
+                )))
+            }
+        };
+        let mut timers = timer.timers.lock().unwrap_or_else(|e| e.into_inner());
+        match self.id {
+            Some(id) => {
+                timers.wakers.insert(id, cx.waker().clone());
+            }
+            None => {
+                let id = timers.next;
+                timers.next += 1;
+                timers.queue.push(Reverse((self.at, id)));
+                timers.wakers.insert(id, cx.waker().clone());
+                self.id = Some(id);
+                timer.changed.notify_one();
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        if let (Some(id), Some(timer)) = (self.id, Timer::get()) {
+            if let Ok(mut timers) = timer.timers.lock() {
+                timers.wakers.remove(&id);
+            }
+        }
+    }
+}